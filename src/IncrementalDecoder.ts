@@ -0,0 +1,123 @@
+import type { Schema } from "./schemas";
+import { sameSchemaBytes, type output } from "./deserialize";
+import { ReadContext } from "./ReadContext";
+
+/**
+ * The result of feeding bytes into an {@link IncrementalDecoder}. If `done` is false,
+ * more bytes are needed before the value can be decoded.
+ */
+export type IncrementalDecodeResult<T> =
+    | { done: false }
+    | { done: true; value: T };
+
+/**
+ * Decodes a static file (the schema followed by the value) from bytes that are handed to it
+ * as they arrive, rather than by pulling from a stream. This is useful when you are driving
+ * your own event loop and cannot wait on a stream for the rest of the data.
+ *
+ * Decoding picks up where it left off each time more bytes arrive, so every byte is only read
+ * once. Calls to {@link IncrementalDecoder.feed} are handled one at a time in the order they
+ * were made, so they don't need to be awaited before the next one. The only read left waiting
+ * between calls is on the decoder's own stream, so it can be dropped at any point.
+ *
+ * Streamed types (promises, iterators, and readable streams) are not supported since their
+ * data is sent after the value.
+ *
+ * @template S - The schema type
+ *
+ * @example
+ * ```typescript
+ * const decoder = new IncrementalDecoder(userSchema);
+ * socket.on("data", async (chunk) => {
+ *     const result = await decoder.feed(chunk);
+ *     if (result.done) console.log(result.value);
+ * });
+ * ```
+ */
+export class IncrementalDecoder<S extends Schema<any>> {
+    private _controller: ReadableStreamDefaultController<Uint8Array> | null =
+        null;
+    private _decoding: Promise<output<S>> | null = null;
+    private _starved: (() => void) | null = null;
+    private _queue: Promise<unknown> = Promise.resolve();
+    private _done = false;
+
+    constructor(private schema: S) {}
+
+    /**
+     * Adds bytes to the decoder and carries on decoding the value. Feeding more bytes once the
+     * value has been decoded throws.
+     *
+     * @param chunk - The next bytes of the payload
+     * @returns Promise resolving to the value if it is complete, or `{ done: false }` if more bytes are needed
+     */
+    feed(chunk: Uint8Array): Promise<IncrementalDecodeResult<output<S>>> {
+        const result = this._queue.then(() => this._feed(chunk));
+        this._queue = result.catch(() => {});
+        return result;
+    }
+
+    private async _feed(
+        chunk: Uint8Array,
+    ): Promise<IncrementalDecodeResult<output<S>>> {
+        if (this._done) {
+            throw new Error("The value has already been decoded");
+        }
+        if (chunk.length === 0) {
+            return { done: false };
+        }
+
+        // Set up the wait before the bytes go in, since the decoder may run out straight away.
+        const starved = new Promise<void>((resolve) => {
+            this._starved = resolve;
+        });
+        if (!this._controller) {
+            const stream = new ReadableStream<Uint8Array>({
+                start: (controller) => {
+                    this._controller = controller;
+                },
+            });
+            this._controller!.enqueue(chunk);
+            const readCtx = new ReadContext(stream.getReader());
+            readCtx.onStarved = () => this._starved?.();
+            this._decoding = this._decode(readCtx);
+        } else {
+            this._controller.enqueue(chunk);
+        }
+
+        const result = await Promise.race([
+            this._decoding!.then((value) => ({ done: true as const, value })),
+            starved.then(() => ({ done: false as const })),
+        ]);
+        this._starved = null;
+        if (result.done) {
+            this._done = true;
+            this._controller!.close();
+        }
+        return result;
+    }
+
+    private async _decode(readCtx: ReadContext): Promise<output<S>> {
+        const { reflectByteReprToSchema } = await import("./reflection");
+        let theirSchema = (await reflectByteReprToSchema(readCtx)) as S;
+        if (!theirSchema.isCompatibleWith(this.schema)) {
+            throw new Error(
+                "Incompatible schema received during deserialization",
+            );
+        }
+        if (sameSchemaBytes(theirSchema.schema, this.schema.schema)) {
+            theirSchema = this.schema;
+        }
+
+        const result = await theirSchema.readFromContext(
+            readCtx,
+            () => {
+                throw new Error(
+                    "Streamed types are not supported by the incremental decoder",
+                );
+            },
+            {},
+        );
+        return result[0];
+    }
+}
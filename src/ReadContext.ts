@@ -1,5 +1,6 @@
 /**
  * The error thrown when attempting to read past the end of the stream.
+ * This is thrown wherever the data ends early, including part way through a length or number.
  */
 export class OutOfDataError extends Error {
     constructor() {
@@ -7,84 +8,198 @@ export class OutOfDataError extends Error {
     }
 }
 
+/**
+ * The error thrown when the stream does not follow the protocol, such as when data is received
+ * for a stream that was never opened or has already been closed.
+ */
+export class ProtocolError extends Error {
+    constructor(message: string) {
+        super(message);
+        this.name = "ProtocolError";
+    }
+}
+
+/**
+ * The error thrown when a value read from the stream isn't valid for its schema, such as an index
+ * past the end of a union or a date that doesn't exist. This means the data is corrupt or wasn't
+ * written by this library.
+ */
+export class DataError extends ProtocolError {
+    /** Where in the stream the bad value starts. */
+    readonly offset: number;
+
+    constructor(message: string, offset: number) {
+        super(`${message} at offset ${offset}`);
+        this.name = "DataError";
+        this.offset = offset;
+    }
+}
+
 export class ReadContext {
     private _slices: (Uint8Array | null)[] = [];
     private _pos = 0;
+    private _consumed = 0;
+
+    /** How deep the schema or value currently being read is nested. */
+    depth = 0;
+
+    /** The deepest a reflected schema or a value is allowed to be nested. */
+    maxDepth = 128;
+
+    /** The most bytes a single read can ask for, so a bad length can't allocate too much. */
+    maxLength = Infinity;
+
+    /**
+     * If true, reads that fit within a single received chunk return a view into that chunk
+     * instead of a copy.
+     */
+    borrowBytes = false;
+
+    /** Called when a read has used up everything received so far and has to wait for more. */
+    onStarved: (() => void) | null = null;
+
+    private _recording: number[] | null = null;
     private _promise: Promise<Uint8Array | null>;
 
     constructor(private reader: ReadableStreamDefaultReader<Uint8Array>) {
-        const r = () =>
+        this._promise = this._startReading();
+    }
+
+    private _startReading() {
+        const slices = this._slices;
+        const r = (): Promise<Uint8Array | null> =>
             this.reader.read().then(({ done, value }) => {
+                if (slices !== this._slices) {
+                    // The context was reset while this read was in flight.
+                    return null;
+                }
                 if (done) {
-                    this._slices.push(null);
+                    slices.push(null);
                     return null;
                 }
-                this._slices.push(value!);
+                slices.push(value!);
                 this._promise = r();
                 return value!;
             });
-        this._promise = r();
+        return r();
+    }
+
+    /**
+     * Points the context at a new reader so it can be reused for another message. Anything left
+     * over from the previous reader is thrown away, and the previous reader is not cancelled.
+     *
+     * @param reader - The reader to read from next
+     */
+    reset(reader: ReadableStreamDefaultReader<Uint8Array>) {
+        this.reader = reader;
+        this._slices = [];
+        this._pos = 0;
+        this._consumed = 0;
+        this._recording = null;
+        this.depth = 0;
+        this._promise = this._startReading();
+    }
+
+    /**
+     * Starts keeping a copy of every byte that is read. Pass what this returns to
+     * stopRecording() to get the bytes back. Recordings can be nested.
+     *
+     * @returns Where the recording starts
+     */
+    startRecording() {
+        if (!this._recording) this._recording = [];
+        return this._recording.length;
+    }
+
+    /**
+     * Stops a recording started with startRecording().
+     *
+     * @param start - What startRecording() returned
+     * @returns The bytes read since the recording started
+     */
+    stopRecording(start: number) {
+        const bytes = Uint8Array.from(this._recording!.slice(start));
+        if (start === 0) this._recording = null;
+        return bytes;
+    }
+
+    private _record(bytes: Uint8Array) {
+        if (!this._recording) return;
+        for (let i = 0; i < bytes.length; i++) {
+            this._recording.push(bytes[i]);
+        }
+    }
+
+    /**
+     * The number of bytes read so far, which is the offset of the next byte in the stream.
+     */
+    get position() {
+        return this._consumed;
     }
 
     async readByte(): Promise<number> {
         for (;;) {
-            if (this._slices.length) {
+            while (this._slices.length) {
                 const slice = this._slices[0];
                 if (slice === null) {
                     throw new OutOfDataError();
                 }
                 if (this._pos < slice.length) {
-                    return slice[this._pos++];
-                } else {
-                    this._slices.shift();
-                    this._pos = 0;
+                    this._consumed++;
+                    const byte = slice[this._pos++];
+                    this._recording?.push(byte);
+                    return byte;
                 }
-            } else {
-                break;
+                this._slices.shift();
+                this._pos = 0;
             }
-        }
 
-        const slice = await this._promise;
-        if (slice === null) {
-            throw new OutOfDataError();
-        }
-        this._pos = 1;
-        if (slice[0] === undefined) {
-            throw new OutOfDataError();
+            // The next chunk is added to the slices when it arrives, so loop around and
+            // read it from there. This means empty chunks aren't mistaken for the end.
+            this.onStarved?.();
+            await this._promise;
         }
-        return slice[0];
     }
 
     async peekByte(): Promise<number> {
         for (;;) {
-            if (this._slices.length) {
+            while (this._slices.length) {
                 const slice = this._slices[0];
                 if (slice === null) {
                     throw new OutOfDataError();
                 }
                 if (this._pos < slice.length) {
                     return slice[this._pos];
-                } else {
-                    this._slices.shift();
-                    this._pos = 0;
                 }
-            } else {
-                break;
+                this._slices.shift();
+                this._pos = 0;
             }
+            this.onStarved?.();
+            await this._promise;
         }
-
-        const slice = await this._promise;
-        if (slice === null) {
-            throw new OutOfDataError();
-        }
-        this._pos = 0;
-        if (slice[0] === undefined) {
-            throw new OutOfDataError();
-        }
-        return slice[0];
     }
 
     async readBytes(len: number): Promise<Uint8Array> {
+        if (len > this.maxLength) {
+            throw new DataError(
+                `Length ${len} is over the limit of ${this.maxLength} bytes`,
+                this._consumed,
+            );
+        }
+        if (this.borrowBytes && this._slices.length) {
+            const slice = this._slices[0];
+            if (slice !== null && slice.length - this._pos >= len) {
+                const view = slice.subarray(this._pos, this._pos + len);
+                this._pos += len;
+                this._consumed += len;
+                if (this._pos >= slice.length) {
+                    this._slices.shift();
+                    this._pos = 0;
+                }
+                this._record(view);
+                return view;
+            }
+        }
         const result = new Uint8Array(len);
         let offset = 0;
         while (offset < len) {
@@ -103,6 +218,7 @@ export class ReadContext {
                         offset,
                     );
                     this._pos += toCopy;
+                    this._consumed += toCopy;
                     offset += toCopy;
                     if (this._pos >= slice.length) {
                         this._slices.shift();
@@ -118,12 +234,14 @@ export class ReadContext {
             if (offset >= len) {
                 break;
             }
+            this.onStarved?.();
             const slice = await this._promise;
             if (slice === null) {
                 throw new OutOfDataError();
             }
             this._pos = 0;
         }
+        this._record(result);
         return result;
     }
 }
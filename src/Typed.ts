@@ -0,0 +1,91 @@
+import type { Writable } from "stream";
+import type { Schema } from "./schemas";
+import {
+    serialize,
+    serializeToUint8Array,
+    type SerializeOptions,
+} from "./serialize";
+import { deserialize, readStaticFile, type output } from "./deserialize";
+
+/**
+ * Carries a value together with the schema that describes it, so the two don't have to be
+ * passed around separately.
+ *
+ * @template S - The schema type
+ *
+ * @example
+ * ```typescript
+ * const user = new Typed(userSchema, { name: "John", age: 30 });
+ * const bytes = await user.serializeToUint8Array();
+ * const copy = await Typed.readStaticFile(userSchema, bytes);
+ * console.log(copy.value); // { name: "John", age: 30 }
+ * ```
+ */
+export class Typed<S extends Schema<any>> {
+    constructor(
+        public readonly schema: S,
+        public readonly value: output<S>,
+    ) {}
+
+    /**
+     * Serializes the value to a stream. See serialize() for more information.
+     *
+     * @param writable - Target stream (Node.js Writable or browser WritableStream)
+     * @param lastUpdateHash - Optional hash of the last schema used, for optimization
+     * @param options - Optional settings for how the data is written
+     * @returns Promise that resolves when serialization is complete
+     */
+    serialize(
+        writable: Writable | WritableStream<Uint8Array>,
+        lastUpdateHash?: string,
+        options?: SerializeOptions,
+    ) {
+        return serialize(
+            this.schema,
+            writable,
+            this.value,
+            lastUpdateHash,
+            options,
+        );
+    }
+
+    /**
+     * Serializes the value to a Uint8Array in the static file format.
+     *
+     * @returns A Uint8Array containing the serialized binary data
+     */
+    serializeToUint8Array() {
+        return serializeToUint8Array(this.schema, this.value);
+    }
+
+    /**
+     * Deserializes a value from a stream and wraps it with its schema. See deserialize() for more information.
+     *
+     * @param schema - Schema defining the expected data structure
+     * @param getReader - Function that returns a ReadableStream for the given schema hash and abort signal
+     * @returns Promise resolving to the wrapped value
+     */
+    static async deserialize<S extends Schema<any>>(
+        schema: S,
+        getReader: (
+            schemaHash: string,
+            abortSignal: AbortSignal,
+        ) => Promise<ReadableStream<Uint8Array>>,
+    ): Promise<Typed<S>> {
+        return new Typed(schema, await deserialize(schema, getReader));
+    }
+
+    /**
+     * Reads a static file and wraps the value with its schema.
+     *
+     * @param schema - Schema defining the expected data structure
+     * @param readerOrPayload - ReadableStream to read from, or a Uint8Array payload
+     * @returns Promise resolving to the wrapped value
+     */
+    static async readStaticFile<S extends Schema<any>>(
+        schema: S,
+        readerOrPayload: ReadableStream<Uint8Array> | Uint8Array,
+    ): Promise<Typed<S>> {
+        return new Typed(schema, await readStaticFile(schema, readerOrPayload));
+    }
+}
@@ -0,0 +1,68 @@
+import { ReadContext, OutOfDataError } from "./ReadContext";
+import { StaticReader } from "./deserialize";
+import {
+    getRollingUintSize,
+    readRollingUintNoAlloc,
+    writeRollingUintNoAlloc,
+} from "./utils";
+
+/**
+ * Joins several serialized messages into one batch. Each message is prefixed with its length, so
+ * they can be split apart again with {@link splitMessages} without reading their schemas. This
+ * is needed since messages written without their schema can't be told apart otherwise.
+ *
+ * @param messages - The messages to join
+ * @returns The batch
+ *
+ * @example
+ * ```typescript
+ * const batch = concatMessages([first, second]);
+ * const [a, b] = await splitMessages(batch);
+ * ```
+ */
+export function concatMessages(messages: Uint8Array[]): Uint8Array {
+    let len = 0;
+    for (const message of messages) {
+        len += getRollingUintSize(message.length) + message.length;
+    }
+    const batch = new Uint8Array(len);
+    let pos = 0;
+    for (const message of messages) {
+        pos = writeRollingUintNoAlloc(message.length, batch, pos);
+        batch.set(message, pos);
+        pos += message.length;
+    }
+    return batch;
+}
+
+/**
+ * Splits a batch made by {@link concatMessages} back into its messages. The messages are views
+ * into the batch rather than copies.
+ *
+ * @param batch - The batch to split
+ * @returns Promise resolving to the messages, in the order they were joined
+ * @throws {OutOfDataError} If the batch ends part way through a message
+ *
+ * @example
+ * ```typescript
+ * for (const message of await splitMessages(batch)) {
+ *     console.log(await readStaticFile(userSchema, message));
+ * }
+ * ```
+ */
+export async function splitMessages(
+    batch: Uint8Array,
+): Promise<Uint8Array[]> {
+    const messages: Uint8Array[] = [];
+    let pos = 0;
+    while (pos < batch.length) {
+        const len = await readRollingUintNoAlloc(
+            new ReadContext(new StaticReader(batch.subarray(pos)).getReader()),
+        );
+        pos += getRollingUintSize(len);
+        if (pos + len > batch.length) throw new OutOfDataError();
+        messages.push(batch.subarray(pos, pos + len));
+        pos += len;
+    }
+    return messages;
+}
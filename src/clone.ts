@@ -0,0 +1,78 @@
+/**
+ * Options for {@link cloneValue}.
+ */
+export type CloneOptions = {
+    /**
+     * If binary data (Uint8Arrays, Buffers, and other typed arrays) should be copied. Defaults
+     * to false, which shares the underlying memory with the original so large payloads are cheap
+     * to clone.
+     */
+    copyBinary?: boolean;
+};
+
+function cloneInner(value: any, copyBinary: boolean): any {
+    if (typeof value !== "object" || value === null) return value;
+
+    if (ArrayBuffer.isView(value)) {
+        if (!copyBinary) return value;
+        if (typeof Buffer !== "undefined" && Buffer.isBuffer(value)) {
+            return Buffer.from(value);
+        }
+        return (value as Uint8Array).slice();
+    }
+    if (value instanceof Date) return new Date(value.getTime());
+    if (Array.isArray(value)) {
+        return value.map((item) => cloneInner(item, copyBinary));
+    }
+    if (value instanceof Map) {
+        const res = new Map();
+        for (const [k, v] of value) {
+            res.set(cloneInner(k, copyBinary), cloneInner(v, copyBinary));
+        }
+        return res;
+    }
+    if (value instanceof Set) {
+        const res = new Set();
+        for (const item of value) {
+            res.add(cloneInner(item, copyBinary));
+        }
+        return res;
+    }
+    if (
+        value instanceof Promise ||
+        value instanceof ReadableStream ||
+        Symbol.asyncIterator in value ||
+        Symbol.iterator in value
+    ) {
+        // These can only be read once, so there is nothing to copy.
+        return value;
+    }
+
+    const res: any = {};
+    for (const key of Object.keys(value)) {
+        res[key] = cloneInner(value[key], copyBinary);
+    }
+    return res;
+}
+
+/**
+ * Deep clones a value so that objects, arrays, Maps, Sets, and Dates in the result can be
+ * changed without affecting the original.
+ *
+ * Binary data is shared with the original by default, so cloning a value holding a large
+ * Uint8Array doesn't copy it. Set `copyBinary` if the clone needs its own copy. Promises,
+ * iterators, and readable streams are always shared since they can only be consumed once.
+ *
+ * @param value - The value to clone
+ * @param options - Optional settings for how binary data is cloned
+ * @returns The cloned value
+ *
+ * @example
+ * ```typescript
+ * const copy = cloneValue({ name: "file.bin", data: new Uint8Array(10_000_000) });
+ * copy.name = "other.bin"; // The original is unchanged, and the data was not copied.
+ * ```
+ */
+export function cloneValue<T>(value: T, options: CloneOptions = {}): T {
+    return cloneInner(value, options.copyBinary ?? false);
+}
@@ -0,0 +1,185 @@
+import { describeSchema } from "./describeSchema";
+import type { ObjectSchemas, Schema } from "./schemas";
+import { comparingRecursive } from "./utils";
+
+function renderPath(path: string[]) {
+    let rendered = "";
+    for (const part of path) {
+        rendered += rendered === "" || part.startsWith("[") ? part : `.${part}`;
+    }
+    return rendered === "" ? "root" : rendered;
+}
+
+function mismatch(
+    received: Schema<any>,
+    expected: Schema<any>,
+    path: string[],
+    out: string[],
+) {
+    out.push(
+        `${renderPath(path)}: expected ${describeSchema(expected)} but received ${describeSchema(received)}`,
+    );
+}
+
+function explainFields(
+    received: ObjectSchemas,
+    expected: ObjectSchemas,
+    path: string[],
+    out: string[],
+    stack: Set<unknown>,
+) {
+    for (const [key, schema] of Object.entries(expected)) {
+        const theirs = received[key];
+        if (theirs) {
+            explain(theirs, schema, [...path, key], out, stack);
+        } else if (schema.name !== "optional") {
+            out.push(
+                `${renderPath([...path, key])}: missing, and it isn't optional`,
+            );
+        }
+    }
+}
+
+function explainEach(
+    received: Schema<any>[],
+    expected: Schema<any>[],
+    path: string[],
+    out: string[],
+    stack: Set<unknown>,
+) {
+    if (received.length !== expected.length) {
+        out.push(
+            `${renderPath(path)}: expected ${expected.length} options or elements but received ${received.length}`,
+        );
+        return;
+    }
+    for (let i = 0; i < expected.length; i++) {
+        explain(received[i], expected[i], [...path, `[${i}]`], out, stack);
+    }
+}
+
+function explain(
+    received: Schema<any>,
+    expected: Schema<any>,
+    path: string[],
+    out: string[],
+    stack: Set<unknown>,
+) {
+    if (received.isCompatibleWith(expected)) return;
+    if (received.name === "recursiveRef" && expected.name === "recursiveRef") {
+        out.push(
+            `${renderPath(path)}: refers to a different level of recursion`,
+        );
+        return;
+    }
+
+    const theirs = received._extraInfo;
+    const ours = expected._extraInfo;
+    if (received.name !== expected.name || stack.has(theirs)) {
+        // Recursive schemas that differ would otherwise be explained forever.
+        mismatch(received, expected, path, out);
+        return;
+    }
+    const before = out.length;
+    if (theirs !== undefined && typeof theirs === "object") stack.add(theirs);
+    try {
+        switch (expected.name) {
+            case "object":
+            case "orderedObject":
+            case "selfDescribingObject":
+            case "sparseObject":
+            case "partial":
+                explainFields(theirs, ours, path, out, stack);
+                break;
+            case "taggedUnion": {
+                const theirTags = Object.keys(theirs).sort();
+                const ourTags = Object.keys(ours).sort();
+                if (theirTags.join(",") !== ourTags.join(",")) {
+                    out.push(
+                        `${renderPath(path)}: expected the tags ${ourTags.join(", ")} but received ${theirTags.join(", ")}`,
+                    );
+                    break;
+                }
+                for (const tag of ourTags) {
+                    explain(theirs[tag], ours[tag], [...path, tag], out, stack);
+                }
+                break;
+            }
+            case "array":
+            case "set":
+            case "iterator":
+            case "promise":
+            case "optional":
+            case "record":
+            case "compressionTable":
+                explain(theirs, ours, path, out, stack);
+                break;
+            case "recursive":
+                // Lets references inside be checked against each other.
+                comparingRecursive.push([theirs, ours]);
+                try {
+                    explain(theirs, ours, path, out, stack);
+                } finally {
+                    comparingRecursive.pop();
+                }
+                break;
+            case "nullable":
+                if (theirs && ours) explain(theirs, ours, path, out, stack);
+                break;
+            case "tuple":
+                explainEach(theirs, ours, path, out, stack);
+                break;
+            case "union":
+                // Options added to the end are fine, since the data never uses them.
+                explainEach(
+                    theirs,
+                    ours.slice(0, theirs.length),
+                    path,
+                    out,
+                    stack,
+                );
+                break;
+            case "map":
+            case "dynRecord":
+                explain(theirs[0], ours[0], [...path, "[key]"], out, stack);
+                explain(theirs[1], ours[1], path, out, stack);
+                break;
+        }
+    } finally {
+        stack.delete(theirs);
+    }
+
+    // Nothing inside was more specific, so describe the whole thing.
+    if (out.length === before) mismatch(received, expected, path, out);
+}
+
+/**
+ * Explains why data written with one schema can't be read with another. Each entry names where
+ * in the schema the problem is and what was expected there, such as
+ * `user.age: expected uint but received string`. The rules are the same ones deserialize()
+ * uses, so an empty result means the data can be read.
+ *
+ * This is useful for checking a new version of a schema against the old one before rolling it
+ * out. Adding optional() fields, widening a uint8() to a uint(), and adding options to the end of
+ * a union are compatible. Changing a type, removing a required field, or removing or reordering
+ * the options of a union is not.
+ *
+ * @param received - The schema the data is written with
+ * @param expected - The schema the reader uses
+ * @returns The incompatibilities, or an empty array if there are none
+ *
+ * @example
+ * ```typescript
+ * const v1 = object({ name: string() });
+ * const v2 = object({ name: string(), age: uint() });
+ * explainIncompatibility(v1, v2); // ["age: missing, and it isn't optional"]
+ * ```
+ */
+export function explainIncompatibility(
+    received: Schema<any>,
+    expected: Schema<any>,
+): string[] {
+    const out: string[] = [];
+    explain(received, expected, [], out, new Set());
+    return out;
+}
@@ -0,0 +1,86 @@
+import type { ObjectSchemas, Schema } from "./schemas";
+
+function describeFields(
+    schemas: ObjectSchemas,
+    sorted: boolean,
+    stack: Set<unknown>,
+) {
+    const keys = Object.keys(schemas);
+    if (sorted) keys.sort((a, b) => a.localeCompare(b));
+    const fields = keys.map((key) => `${key}: ${describe(schemas[key], stack)}`);
+    return `{${fields.join(", ")}}`;
+}
+
+function describe(schema: Schema<any>, stack: Set<unknown>): string {
+    const extra = schema._extraInfo;
+    if (extra !== undefined && typeof extra === "object") {
+        // Recursive schemas made with lazy() would otherwise never finish.
+        if (stack.has(extra)) return "...";
+        stack.add(extra);
+    }
+    try {
+        return describeInner(schema, stack);
+    } finally {
+        stack.delete(extra);
+    }
+}
+
+function describeInner(schema: Schema<any>, stack: Set<unknown>): string {
+    const extra = schema._extraInfo;
+    switch (schema.name) {
+        case "object":
+        case "selfDescribingObject":
+        case "sparseObject":
+        case "partial":
+        case "taggedUnion":
+            return `${schema.name}(${describeFields(extra, true, stack)})`;
+        case "orderedObject":
+            return `${schema.name}(${describeFields(extra, false, stack)})`;
+        case "array":
+        case "set":
+        case "iterator":
+        case "promise":
+        case "optional":
+        case "record":
+        case "compressionTable":
+        case "recursive":
+            return `${schema.name}(${describe(extra, stack)})`;
+        case "nullable":
+            return extra ? `nullable(${describe(extra, stack)})` : "nullable";
+        case "union":
+            return `union(${(extra as Schema<any>[])
+                .map((option) => describe(option, stack))
+                .join("|")})`;
+        case "tuple":
+            return `tuple(${(extra as Schema<any>[])
+                .map((element) => describe(element, stack))
+                .join(", ")})`;
+        case "map":
+        case "dynRecord":
+            return `${schema.name}(${describe(extra[0], stack)}, ${describe(extra[1], stack)})`;
+        case "typedArray":
+        case "fixedBytes":
+            return `${schema.name}(${extra})`;
+        default:
+            return schema.name;
+    }
+}
+
+/**
+ * Renders a schema as a short, readable type string, such as
+ * `object({age: uint, name: string})`. Object fields are listed in the order they are written
+ * on the wire. The output is stable, so it can be used in error messages and snapshot tests.
+ *
+ * @param schema - The schema to describe
+ * @returns The type string
+ *
+ * @example
+ * ```typescript
+ * describeSchema(nullable(union(string(), uint()))); // "nullable(union(string|uint))"
+ * describeSchema(object({ name: string(), tags: array(string()) }));
+ * // "object({name: string, tags: array(string)})"
+ * ```
+ */
+export function describeSchema(schema: Schema<any>): string {
+    return describe(schema, new Set());
+}
@@ -1,8 +1,22 @@
 import type { Schema } from "./schemas";
-import { ReadContext, OutOfDataError } from "./ReadContext";
+import { ReadContext, OutOfDataError, ProtocolError } from "./ReadContext";
+import {
+    decodeHookKey,
+    getRollingUintSize,
+    readRollingUintNoAlloc,
+} from "./utils";
+import { parseHeaderFlags } from "./header";
 
 const weakHashMap = new WeakMap<Uint8Array, string>();
 
+export function sameSchemaBytes(a: Uint8Array, b: Uint8Array) {
+    if (a.length !== b.length) return false;
+    for (let i = 0; i < a.length; i++) {
+        if (a[i] !== b[i]) return false;
+    }
+    return true;
+}
+
 /**
  * The output type of a schema. Given a schema `T`, `output<T>` is the type of data
  * that schema represents.
@@ -46,6 +60,80 @@ export async function getHash(schema: Schema<any>) {
     return hash;
 }
 
+/**
+ * Options for {@link deserialize} and {@link readStaticFile}.
+ */
+export type DeserializeOptions = {
+    /**
+     * Called with each value as it is read, innermost values first. Whatever is returned is
+     * used in place of the value, which is useful for interning strings or applying defaults.
+     * If this throws, reading fails with that error.
+     */
+    onValue?: (schema: Schema<any>, value: any) => any;
+
+    /**
+     * The deepest a schema or value read from the stream can be nested, including schemas sent
+     * alongside values for any(). Objects, arrays, unions, any() values, and each level of a
+     * recursive() value count towards this. Defaults to 128.
+     */
+    maxDepth?: number;
+
+    /**
+     * The most bytes a single string, byte array, or other length prefixed value can take up.
+     * Lengths over this are rejected before anything is allocated for them. Defaults to no limit.
+     */
+    maxLength?: number;
+
+    /**
+     * If true, a schema sent with the data must be byte for byte the same as the schema passed
+     * in, rather than just compatible with it. This catches a producer and consumer drifting
+     * apart before any of the value is read. Defaults to false.
+     */
+    strictSchema?: boolean;
+
+    /**
+     * If true, byte arrays are returned as views into the received data where they fit within
+     * one chunk, rather than being copied. This avoids copying large payloads, and every byte
+     * array read by {@link readStaticFile} from a Uint8Array is a view. The results share memory
+     * with the input though, so changing one changes the other. Defaults to false.
+     */
+    borrowBytes?: boolean;
+};
+
+function makeReadContext(
+    reader: ReadableStreamDefaultReader<Uint8Array>,
+    options: DeserializeOptions,
+) {
+    const ctx = new ReadContext(reader);
+    if (options.maxDepth !== undefined) ctx.maxDepth = options.maxDepth;
+    if (options.maxLength !== undefined) ctx.maxLength = options.maxLength;
+    if (options.borrowBytes) ctx.borrowBytes = true;
+    return ctx;
+}
+
+function pickReceivedSchema<S extends Schema<any>>(
+    received: S,
+    expected: S,
+    options: DeserializeOptions,
+) {
+    // If the bytes match, the layout is identical. Keep ours since it knows
+    // which fields of self describing objects we actually care about.
+    if (sameSchemaBytes(received.schema, expected.schema)) return expected;
+    if (options.strictSchema) {
+        throw new ProtocolError(
+            "Schema received during deserialization does not match the expected schema",
+        );
+    }
+    if (!received.isCompatibleWith(expected)) {
+        throw new Error("Incompatible schema received during deserialization");
+    }
+    return received;
+}
+
+function makeScratchPad(options: DeserializeOptions) {
+    return options.onValue ? { [decodeHookKey]: options.onValue } : {};
+}
+
 /**
  * Deserializes data from a stream using the provided schema.
  * Handles streaming deserialization with support for complex nested data types.
@@ -59,6 +147,7 @@ export async function getHash(schema: Schema<any>) {
  * @template S - The schema type
  * @param schema - Schema defining the expected data structure
  * @param getReader - Function that returns a ReadableStream for the given schema hash and abort signal
+ * @param options - Optional settings for how the data is read
  * @returns Promise resolving to the deserialized data of type output<S>
  *
  * @example
@@ -83,35 +172,34 @@ export async function deserialize<S extends Schema<any>>(
         schemaHash: string,
         abortSignal: AbortSignal,
     ) => Promise<ReadableStream<Uint8Array>>,
+    options: DeserializeOptions = {},
 ): Promise<output<S>> {
     const schemaHash = await getHash(schema);
     const abortController = new AbortController();
     const handlers = new Map<number, (ctx: ReadContext) => Promise<void>>();
 
     const reader = await getReader(schemaHash, abortController.signal);
-    const readCtx = new ReadContext(reader.getReader());
+    const readCtx = makeReadContext(reader.getReader(), options);
 
-    const payloadHasSchema = await readCtx.readByte();
-    if (payloadHasSchema === 1) {
+    const flags = parseHeaderFlags(await readCtx.readByte());
+    if (flags.hasSchema) {
         // Use reflection to read the schema.
         const { reflectByteReprToSchema } = await import("./reflection");
         const newSchema = (await reflectByteReprToSchema(readCtx)) as S;
-        if (!newSchema.isCompatibleWith(schema)) {
-            throw new Error(
-                "Incompatible schema received during deserialization",
-            );
-        }
-        schema = newSchema;
+        schema = pickReceivedSchema(newSchema, schema, options);
     }
 
-    const disconnectHandlers = new Map<number, () => void>();
+    const disconnectHandlers = new Map<number, (err: Error) => void>();
 
     let usages = 0;
     const hijackReadContext = (
         id: number,
         fn: (ctx: ReadContext) => Promise<void>,
-        onDisconnect: () => void,
+        onDisconnect: (err: Error) => void,
     ) => {
+        if (handlers.has(id)) {
+            throw new ProtocolError(`Stream ${id} was opened twice`);
+        }
         usages++;
         handlers.set(id, fn);
         disconnectHandlers.set(id, onDisconnect);
@@ -131,30 +219,48 @@ export async function deserialize<S extends Schema<any>>(
         };
     };
 
-    const result = await schema.readFromContext(readCtx, hijackReadContext, {});
+    const result = await schema.readFromContext(
+        readCtx,
+        hijackReadContext,
+        makeScratchPad(options),
+    );
     if (usages === 0) {
         // Abort now.
         abortController.abort();
     }
 
     (async () => {
+        let expectedSeq = 0;
         try {
             while (usages > 0) {
                 const idHigh = await readCtx.readByte();
                 const idLow = await readCtx.readByte();
                 const id = (idHigh << 8) | idLow;
 
+                if (flags.sequenceNumbers) {
+                    const seq = await readRollingUintNoAlloc(readCtx);
+                    if (seq !== expectedSeq) {
+                        throw new ProtocolError(
+                            `Expected chunk ${expectedSeq} but received chunk ${seq}`,
+                        );
+                    }
+                    expectedSeq++;
+                }
+
+                // Frames don't carry their length, so if nothing is waiting on this
+                // stream we can't skip past it.
                 const handler = handlers.get(id);
-                if (handler) {
-                    await handler(readCtx);
+                if (!handler) {
+                    throw new ProtocolError(
+                        `Received data for unknown or closed stream ${id}`,
+                    );
                 }
+                await handler(readCtx);
             }
         } catch (e) {
-            if (!(e instanceof OutOfDataError)) {
-                throw e;
-            }
+            abortController.abort();
             for (const disconnectHandler of disconnectHandlers.values()) {
-                disconnectHandler();
+                disconnectHandler(e as Error);
             }
         }
     })();
@@ -162,6 +268,73 @@ export async function deserialize<S extends Schema<any>>(
     return result[0];
 }
 
+/**
+ * Deserializes an array from a stream one element at a time, yielding each element as soon as it
+ * has been read. This lets a consumer start on the first records of a long array before the rest
+ * have arrived, and only one element is held in memory at a time. The array is written the same
+ * way as with deserialize(), so nothing changes on the sending side.
+ *
+ * Streamed types (promises, iterators, and readable streams) are not supported inside the array,
+ * since their data is sent after the array. Breaking out of the loop early aborts the reader.
+ *
+ * @template S - The array schema type
+ * @param schema - An array() schema
+ * @param getReader - Function that returns a ReadableStream for the given schema hash and abort signal
+ * @param options - Optional settings for how the data is read
+ * @returns An async iterable of the array's elements
+ *
+ * @example
+ * ```typescript
+ * const logSchema = array(object({ level: string(), message: string() }));
+ * for await (const entry of deserializeArray(logSchema, getReader)) {
+ *     console.log(entry.message);
+ * }
+ * ```
+ */
+export async function* deserializeArray<S extends Schema<any[]>>(
+    schema: S,
+    getReader: (
+        schemaHash: string,
+        abortSignal: AbortSignal,
+    ) => Promise<ReadableStream<Uint8Array>>,
+    options: DeserializeOptions = {},
+): AsyncGenerator<output<S>[number], void, undefined> {
+    if (schema.name !== "array") {
+        throw new Error("deserializeArray() needs an array schema");
+    }
+    const schemaHash = await getHash(schema);
+    const abortController = new AbortController();
+    try {
+        const reader = await getReader(schemaHash, abortController.signal);
+        const readCtx = makeReadContext(reader.getReader(), options);
+
+        const flags = parseHeaderFlags(await readCtx.readByte());
+        if (flags.hasSchema) {
+            const { reflectByteReprToSchema } = await import("./reflection");
+            const newSchema = (await reflectByteReprToSchema(readCtx)) as S;
+            schema = pickReceivedSchema(newSchema, schema, options);
+        }
+
+        const elements = schema._extraInfo as Schema<any>;
+        const scratchPad = makeScratchPad(options);
+        const len = await readRollingUintNoAlloc(readCtx);
+        for (let i = 0; i < len; i++) {
+            const [value] = await elements.readFromContext(
+                readCtx,
+                () => {
+                    throw new Error(
+                        "Streamed types are not supported when reading an array element by element",
+                    );
+                },
+                scratchPad,
+            );
+            yield value;
+        }
+    } finally {
+        abortController.abort();
+    }
+}
+
 export class StaticReader extends ReadableStream<Uint8Array> {
     pos = 0;
 
@@ -172,10 +345,10 @@ export class StaticReader extends ReadableStream<Uint8Array> {
                     controller.close();
                     return;
                 }
-                const chunkSize = 65536;
-                const end = Math.min(this.pos + chunkSize, this.data.length);
-                const chunk = this.data.slice(this.pos, end);
-                this.pos = end;
+                // The data is already in memory, so hand it over as a single view rather than
+                // copying it out in pieces.
+                const chunk = this.data.subarray(this.pos);
+                this.pos = this.data.length;
                 controller.enqueue(chunk);
             },
         });
@@ -188,6 +361,7 @@ export class StaticReader extends ReadableStream<Uint8Array> {
  * @template S - The schema type
  * @param schema - Schema defining the expected data structure
  * @param readerOrPayload - ReadableStream to read from, or a Uint8Array payload
+ * @param options - Optional settings for how the data is read
  * @returns Promise resolving to the deserialized data of type output<S>
  *
  * @example
@@ -207,17 +381,56 @@ export class StaticReader extends ReadableStream<Uint8Array> {
 export async function readStaticFile<S extends Schema<any>>(
     schema: S,
     readerOrPayload: ReadableStream<Uint8Array> | Uint8Array,
+    options: DeserializeOptions = {},
 ): Promise<output<S>> {
     if (readerOrPayload instanceof Uint8Array) {
         readerOrPayload = new StaticReader(readerOrPayload);
     }
+    const readCtx = makeReadContext(readerOrPayload.getReader(), options);
+    return readStaticFileFromContext(schema, readCtx, options);
+}
 
-    const readCtx = new ReadContext(readerOrPayload.getReader());
+/**
+ * Reads a static file from the start of some bytes, and reports how many bytes it took up. This
+ * is useful when the file is followed by other data, since readStaticFile() ignores anything
+ * after the file.
+ *
+ * @template S - The schema type
+ * @param schema - Schema defining the expected data structure
+ * @param bytes - The bytes starting with the static file
+ * @param options - Optional settings for how the data is read
+ * @returns Promise resolving to the value and the number of bytes the file took up
+ *
+ * @example
+ * ```typescript
+ * const { value, consumed } = await readStaticFileFromBytes(userSchema, bytes);
+ * const rest = bytes.subarray(consumed);
+ * ```
+ */
+export async function readStaticFileFromBytes<S extends Schema<any>>(
+    schema: S,
+    bytes: Uint8Array,
+    options: DeserializeOptions = {},
+): Promise<{ value: output<S>; consumed: number }> {
+    const readCtx = makeReadContext(
+        new StaticReader(bytes).getReader(),
+        options,
+    );
+    const value = await readStaticFileFromContext(schema, readCtx, options);
+    return { value, consumed: readCtx.position };
+}
+
+async function readStaticFileFromContext<S extends Schema<any>>(
+    schema: S,
+    readCtx: ReadContext,
+    options: DeserializeOptions,
+): Promise<output<S>> {
     const { reflectByteReprToSchema } = await import("./reflection");
-    const theirSchema = (await reflectByteReprToSchema(readCtx)) as S;
-    if (!theirSchema.isCompatibleWith(schema)) {
-        throw new Error("Incompatible schema received during deserialization");
-    }
+    const theirSchema = pickReceivedSchema(
+        (await reflectByteReprToSchema(readCtx)) as S,
+        schema,
+        options,
+    );
 
     let usages = 0;
     const handlers = new Map<number, (ctx: ReadContext) => Promise<void>>();
@@ -225,6 +438,9 @@ export async function readStaticFile<S extends Schema<any>>(
         id: number,
         fn: (ctx: ReadContext) => Promise<void>,
     ) => {
+        if (handlers.has(id)) {
+            throw new ProtocolError(`Stream ${id} was opened twice`);
+        }
         usages++;
         handlers.set(id, fn);
         let cleanedUp = false;
@@ -240,7 +456,7 @@ export async function readStaticFile<S extends Schema<any>>(
     const result = await theirSchema.readFromContext(
         readCtx,
         hijackReadContext,
-        {},
+        makeScratchPad(options),
     );
 
     while (usages > 0) {
@@ -249,10 +465,130 @@ export async function readStaticFile<S extends Schema<any>>(
         const id = (idHigh << 8) | idLow;
 
         const handler = handlers.get(id);
-        if (handler) {
-            await handler(readCtx);
+        if (!handler) {
+            throw new ProtocolError(
+                `Received data for unknown or closed stream ${id}`,
+            );
         }
+        await handler(readCtx);
     }
 
     return result[0];
 }
+
+/**
+ * Reads just a schema from the start of some bytes, such as a schema stored on its own or the
+ * start of a static file. This is useful for tooling that inspects or caches schemas.
+ *
+ * @param bytes - The bytes starting with the schema
+ * @returns Promise resolving to the schema and the number of bytes it took up
+ * @throws {OutOfDataError} If the bytes end part way through the schema
+ *
+ * @example
+ * ```typescript
+ * const { schema, consumed } = await readSchema(storedSchemaBytes);
+ * console.log(schema.isCompatibleWith(userSchema), consumed);
+ * ```
+ */
+export async function readSchema(
+    bytes: Uint8Array,
+): Promise<{ schema: Schema<any>; consumed: number }> {
+    const readCtx = new ReadContext(new StaticReader(bytes).getReader());
+    const { reflectByteReprToSchema } = await import("./reflection");
+    const schema = await reflectByteReprToSchema(readCtx);
+    return { schema, consumed: readCtx.position };
+}
+
+/**
+ * Reads a blob written by serializeToBlob(). The length at the start of the blob is checked
+ * against the rest of the data, so a truncated or padded blob is rejected.
+ *
+ * @template S - The schema type
+ * @param schema - Schema defining the expected data structure
+ * @param blob - The blob to read
+ * @returns Promise resolving to the deserialized data of type output<S>
+ *
+ * @example
+ * ```typescript
+ * const user = await readBlob(userSchema, await kv.get("user:1"));
+ * ```
+ */
+export async function readBlob<S extends Schema<any>>(
+    schema: S,
+    blob: Uint8Array,
+): Promise<output<S>> {
+    const len = await readRollingUintNoAlloc(
+        new ReadContext(new StaticReader(blob).getReader()),
+    );
+    const prefixLen = getRollingUintSize(len);
+    if (blob.length - prefixLen !== len) {
+        throw new Error("Blob length does not match the length of the data");
+    }
+    return readStaticFile(schema, blob.subarray(prefixLen));
+}
+
+/**
+ * Reads a value that was written without its schema, when it could have been written with any one
+ * of a few known schemas. Each candidate is tried in order, and the first that reads the whole
+ * payload without any bytes left over is used.
+ *
+ * Be careful with similar schemas. Data written with one schema can sometimes be read cleanly by
+ * another (for example, a uint and a uint8 with a small value), in which case the first of them
+ * in the list wins. Streamed types (promises, iterators, and readable streams) are not supported.
+ *
+ * If no candidate matches, an AggregateError is thrown. Its errors are what each candidate failed
+ * with, in the same order as the candidates.
+ *
+ * @template S - The candidate schema types
+ * @param candidates - The schemas the payload might have been written with
+ * @param payload - The encoded value, without a schema before it
+ * @returns Promise resolving to the schema that matched and the value it read
+ *
+ * @example
+ * ```typescript
+ * const [schema, value] = await readWithCandidates(
+ *   [userSchema, adminSchema],
+ *   payload,
+ * );
+ * ```
+ */
+export async function readWithCandidates<S extends Schema<any>[]>(
+    candidates: [...S],
+    payload: Uint8Array,
+): Promise<[S[number], output<S[number]>]> {
+    const errors: Error[] = [];
+    for (const candidate of candidates) {
+        const readCtx = new ReadContext(new StaticReader(payload).getReader());
+        try {
+            const [value] = await candidate.readFromContext(
+                readCtx,
+                () => {
+                    throw new Error(
+                        "Streamed types are not supported when reading with candidates",
+                    );
+                },
+                {},
+            );
+
+            // Make sure the candidate read everything.
+            try {
+                await readCtx.readByte();
+            } catch (err) {
+                if (err instanceof OutOfDataError) {
+                    return [candidate, value];
+                }
+                throw err;
+            }
+            errors.push(new Error("data left over after reading"));
+        } catch (err) {
+            errors.push(err as Error);
+        }
+    }
+    const reasons = errors.map(
+        (err, i) => `${candidates[i].name}: ${err.message}`,
+    );
+    throw new AggregateError(
+        errors,
+        `Payload did not match any candidate schema: ${reasons.join("; ")}`,
+    );
+}
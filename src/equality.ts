@@ -0,0 +1,160 @@
+import type { Schema } from "./schemas";
+import { deserialize, StaticReader } from "./deserialize";
+
+function isBuffer(value: unknown) {
+    return typeof Buffer !== "undefined" && Buffer.isBuffer(value);
+}
+
+function bytesEqual(a: Uint8Array, b: Uint8Array) {
+    if (a.length !== b.length) return false;
+    for (let i = 0; i < a.length; i++) {
+        if (a[i] !== b[i]) return false;
+    }
+    return true;
+}
+
+/**
+ * Checks if two deserialized values are the same. Arrays, objects, Maps, Sets, Dates, and binary
+ * data are compared by their contents rather than by reference, and NaN is treated as equal to
+ * itself.
+ *
+ * Promises, iterators, and readable streams are only equal if they are the same instance.
+ *
+ * @param a - The first value
+ * @param b - The second value
+ * @returns True if the values are the same
+ *
+ * @example
+ * ```typescript
+ * valuesEqual({ tags: ["a", "b"] }, { tags: ["a", "b"] }); // true
+ * ```
+ */
+export function valuesEqual(a: unknown, b: unknown): boolean {
+    if (Object.is(a, b)) return true;
+    if (typeof a !== typeof b || typeof a !== "object") return false;
+    if (a === null || b === null) return false;
+
+    if (a instanceof Uint8Array) {
+        if (!(b instanceof Uint8Array)) return false;
+        if (isBuffer(a) !== isBuffer(b)) return false;
+        return bytesEqual(a, b);
+    }
+    if (ArrayBuffer.isView(a)) {
+        // Other typed arrays, compared element by element so that NaN matches itself.
+        if (!ArrayBuffer.isView(b) || a.constructor !== b.constructor) {
+            return false;
+        }
+        const aItems = a as unknown as ArrayLike<number>;
+        const bItems = b as unknown as ArrayLike<number>;
+        if (aItems.length !== bItems.length) return false;
+        for (let i = 0; i < aItems.length; i++) {
+            if (!Object.is(aItems[i], bItems[i])) return false;
+        }
+        return true;
+    }
+    if (a instanceof Date) {
+        return b instanceof Date && Object.is(a.getTime(), b.getTime());
+    }
+    if (Array.isArray(a)) {
+        if (!Array.isArray(b) || a.length !== b.length) return false;
+        return a.every((item, i) => valuesEqual(item, b[i]));
+    }
+    if (a instanceof Map) {
+        if (!(b instanceof Map) || a.size !== b.size) return false;
+        const bEntries = Array.from(b.entries());
+
+        // Each entry in b can only be matched once, or two equal entries in a could share it.
+        const matched = new Set<unknown>();
+        for (const [key, value] of a.entries()) {
+            // Keys can be objects, so fall back to a search if it isn't found directly.
+            if (
+                b.has(key) &&
+                !matched.has(key) &&
+                valuesEqual(value, b.get(key))
+            ) {
+                matched.add(key);
+                continue;
+            }
+            const found = bEntries.find(
+                ([bKey, bValue]) =>
+                    !matched.has(bKey) &&
+                    valuesEqual(key, bKey) &&
+                    valuesEqual(value, bValue),
+            );
+            if (!found) return false;
+            matched.add(found[0]);
+        }
+        return true;
+    }
+    if (a instanceof Set) {
+        if (!(b instanceof Set) || a.size !== b.size) return false;
+        const bItems = Array.from(b);
+        const matched = new Set<unknown>();
+        for (const item of a) {
+            if (b.has(item) && !matched.has(item)) {
+                matched.add(item);
+                continue;
+            }
+            const index = bItems.findIndex(
+                (bItem) => !matched.has(bItem) && valuesEqual(item, bItem),
+            );
+            if (index === -1) return false;
+            matched.add(bItems[index]);
+        }
+        return true;
+    }
+    if (
+        a instanceof Promise ||
+        a instanceof ReadableStream ||
+        Symbol.asyncIterator in (a as object) ||
+        Symbol.iterator in (a as object)
+    ) {
+        // These can only be compared by reading them, which would consume them.
+        return false;
+    }
+    if (
+        Array.isArray(b) ||
+        b instanceof Map ||
+        b instanceof Set ||
+        b instanceof Date
+    ) {
+        return false;
+    }
+
+    const aKeys = Object.keys(a as object);
+    const bKeys = Object.keys(b as object);
+    if (aKeys.length !== bKeys.length) return false;
+    for (const key of aKeys) {
+        if (!Object.prototype.hasOwnProperty.call(b, key)) return false;
+        if (!valuesEqual((a as any)[key], (b as any)[key])) return false;
+    }
+    return true;
+}
+
+/**
+ * Checks if two payloads written by serialize() hold the same data. Whether or not each payload
+ * includes the schema doesn't matter, so this can be used to check that both ways of sending the
+ * data agree.
+ *
+ * @param schema - Schema defining the expected data structure
+ * @param a - The first payload
+ * @param b - The second payload
+ * @returns Promise resolving to true if both payloads decode to the same data
+ *
+ * @example
+ * ```typescript
+ * await payloadsEqual(userSchema, withSchema, withoutSchema); // true
+ * ```
+ */
+export async function payloadsEqual(
+    schema: Schema<any>,
+    a: Uint8Array,
+    b: Uint8Array,
+): Promise<boolean> {
+    const [aValue, bValue] = await Promise.all(
+        [a, b].map((payload) =>
+            deserialize(schema, async () => new StaticReader(payload)),
+        ),
+    );
+    return valuesEqual(aValue, bValue);
+}
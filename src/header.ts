@@ -0,0 +1,89 @@
+import { OutOfDataError } from "./ReadContext";
+
+// The bits of the first byte written by serialize.
+export const headerFlags = {
+    hasSchema: 0x01,
+    sequenceNumbers: 0x02,
+};
+
+const knownFlags = headerFlags.hasSchema | headerFlags.sequenceNumbers;
+
+// The top 4 bits hold the format version. Payloads from before versioning have these
+// bits clear, so they read as version 0. Bump this when the format changes in a way older
+// readers can't handle, so that they reject it instead of misreading it.
+export const formatVersion = 1;
+
+// Headers with interned schemas contain back-references that version 0 readers don't know.
+// Everything else is still written as version 0 so that those readers can keep reading it.
+export const internedSchemaVersion = 1;
+export const versionShift = 4;
+const versionMask = 0xf0;
+
+/**
+ * The flags at the start of a payload written by serialize().
+ */
+export type HeaderFlags = {
+    /** If the schema is written after the flags. */
+    hasSchema: boolean;
+
+    /** If each streamed chunk is prefixed with a sequence number. */
+    sequenceNumbers: boolean;
+
+    /** The version of the format the payload was written with. */
+    version: number;
+};
+
+/**
+ * Parses the flags byte at the start of a payload.
+ *
+ * @param byte - The first byte of the payload
+ * @returns The parsed flags
+ * @throws {Error} If the version is newer than this library supports, or any unknown bits are set
+ */
+export function parseHeaderFlags(byte: number): HeaderFlags {
+    const version = (byte & versionMask) >> versionShift;
+    if (version > formatVersion) {
+        throw new Error(
+            `Unsupported format version ${version} at the start of the payload, ` +
+                `the newest supported version is ${formatVersion}. ` +
+                "If this is a static file use readStaticFile instead",
+        );
+    }
+    if (byte & ~knownFlags & ~versionMask) {
+        // Static files and blobs start with the schema rather than the flags.
+        throw new Error(
+            `Invalid flags ${byte} at the start of the payload, ` +
+                "if this is a static file use readStaticFile instead",
+        );
+    }
+    return {
+        hasSchema: (byte & headerFlags.hasSchema) !== 0,
+        sequenceNumbers: (byte & headerFlags.sequenceNumbers) !== 0,
+        version,
+    };
+}
+
+/**
+ * Reads the header of a payload written by serialize() without reading the schema or the value.
+ * This is useful for routers that need to decide how to handle a message before decoding it.
+ *
+ * @param payload - The payload, or at least the start of it
+ * @returns The flags and the number of bytes they took up
+ * @throws {OutOfDataError} If the payload is empty
+ * @throws {Error} If the version is newer than this library supports, or any unknown bits are set
+ *
+ * @example
+ * ```typescript
+ * const { flags } = inspectHeader(payload);
+ * if (!flags.hasSchema) {
+ *     // The sender thinks we already have the schema.
+ * }
+ * ```
+ */
+export function inspectHeader(payload: Uint8Array): {
+    flags: HeaderFlags;
+    consumed: number;
+} {
+    if (payload.length === 0) throw new OutOfDataError();
+    return { flags: parseHeaderFlags(payload[0]), consumed: 1 };
+}
@@ -2,7 +2,40 @@ export {
     serialize,
     serializeToBuffer,
     serializeToUint8Array,
+    serializeToUint8ArraySync,
+    serializeToBlob,
+    getEncodedSize,
+    type EncodedSizeReport,
+    type SerializeOptions,
 } from "./serialize";
-export { OutOfDataError } from "./ReadContext";
-export { deserialize, output, getHash, readStaticFile } from "./deserialize";
+export { DataError, OutOfDataError, ProtocolError } from "./ReadContext";
+export { inspectHeader, type HeaderFlags } from "./header";
+export {
+    IncrementalDecoder,
+    type IncrementalDecodeResult,
+} from "./IncrementalDecoder";
+export {
+    deserialize,
+    deserializeArray,
+    output,
+    getHash,
+    readStaticFile,
+    readStaticFileFromBytes,
+    readSchema,
+    readBlob,
+    readWithCandidates,
+    type DeserializeOptions,
+} from "./deserialize";
 export * from "./schemas";
+export { toJsonSchema, type JsonSchema } from "./jsonSchema";
+export { resolveStreams, resolveStreamedData } from "./resolveStreams";
+export { Typed } from "./Typed";
+export { valuesEqual, payloadsEqual } from "./equality";
+export { mergeValues, type MergeOptions } from "./merge";
+export { cloneValue, type CloneOptions } from "./clone";
+export { toPrettyJson, fromPrettyJson } from "./prettyJson";
+export { streamChunks } from "./streamChunks";
+export { concatMessages, splitMessages } from "./batch";
+export { toRustTypes } from "./rustTypes";
+export { describeSchema } from "./describeSchema";
+export { explainIncompatibility } from "./compatibility";
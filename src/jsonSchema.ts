@@ -0,0 +1,247 @@
+import { getMetadata, type ObjectSchemas, type Schema } from "./schemas";
+
+/**
+ * A JSON Schema document, as returned by {@link toJsonSchema}.
+ */
+export type JsonSchema = { [key: string]: any };
+
+// Made for each toJsonSchema() call. recursive() schemas are put in $defs and referred to with
+// $ref, since they can't be written out inline, so those are kept here keyed by the inner schema.
+class Converter {
+    defNames = new Map<Schema<any>, string>();
+    defs: { [name: string]: JsonSchema } = {};
+
+    convert(schema: Schema<any>): JsonSchema {
+        const res = this._convertInner(schema);
+        const description = getMetadata(schema)?.description;
+        if (description !== undefined) {
+            res.description = description;
+        }
+        return res;
+    }
+
+    private _convertObject(schemas: ObjectSchemas): JsonSchema {
+        const properties: { [key: string]: JsonSchema } = {};
+        const required: string[] = [];
+        const keys = Object.keys(schemas).sort((a, b) => a.localeCompare(b));
+        for (const key of keys) {
+            properties[key] = this.convert(schemas[key]);
+            if (schemas[key].name !== "optional") {
+                required.push(key);
+            }
+        }
+        return { type: "object", properties, required };
+    }
+
+    private _convertInner(schema: Schema<any>): JsonSchema {
+        switch (schema.name) {
+            case "object":
+            case "orderedObject":
+            case "selfDescribingObject":
+            case "sparseObject":
+                return this._convertObject(schema._extraInfo as ObjectSchemas);
+            case "partial":
+                return {
+                    ...this._convertObject(schema._extraInfo as ObjectSchemas),
+                    required: [],
+                };
+            case "array":
+            case "iterator":
+                // Iterators are sent as they are consumed, but end up as a list of items.
+                return {
+                    type: "array",
+                    items: this.convert(schema._extraInfo),
+                };
+            case "set":
+                return {
+                    type: "array",
+                    items: this.convert(schema._extraInfo),
+                    uniqueItems: true,
+                };
+            case "tuple": {
+                const elements = schema._extraInfo as Schema<any>[];
+                return {
+                    type: "array",
+                    prefixItems: elements.map((element) =>
+                        this.convert(element),
+                    ),
+                    minItems: elements.length,
+                    maxItems: elements.length,
+                };
+            }
+            case "typedArray":
+                return {
+                    type: "array",
+                    items: {
+                        type: schema._extraInfo.startsWith("float")
+                            ? "number"
+                            : "integer",
+                    },
+                };
+            case "booleanArray":
+                return { type: "array", items: { type: "boolean" } };
+            case "string":
+            case "potentiallyFloatString":
+                return { type: "string" };
+            case "uint8array":
+            case "buffer":
+            case "fixedBytes":
+            case "readableStream":
+                return { type: "string", contentEncoding: "base64" };
+            case "promise":
+            case "compressionTable":
+            case "pipe":
+                return this.convert(schema._extraInfo);
+            case "recursive": {
+                const inner = schema._extraInfo as Schema<any>;
+                let name = this.defNames.get(inner);
+                if (name === undefined) {
+                    name = `recursive${this.defNames.size}`;
+                    this.defNames.set(inner, name);
+                    this.defs[name] = this.convert(inner);
+                }
+                return { $ref: `#/$defs/${name}` };
+            }
+            case "recursiveRef": {
+                const name = this.defNames.get(schema._extraInfo.inner);
+                if (name === undefined) {
+                    throw new Error(
+                        "Cannot convert a recursive reference outside of its recursive schema",
+                    );
+                }
+                return { $ref: `#/$defs/${name}` };
+            }
+            case "boolean":
+                return { type: "boolean" };
+            case "uint8":
+                return { type: "integer", minimum: 0, maximum: 255 };
+            case "uint":
+                return { type: "integer", minimum: 0 };
+            case "int":
+                return { type: "integer" };
+            case "float":
+            case "float32":
+                return { type: "number" };
+            case "bigint":
+                // JSON numbers can't hold every bigint, so this is only an approximation.
+                return { type: "integer", minimum: 0 };
+            case "int128":
+            case "arbitraryBigint":
+                return { type: "integer" };
+            case "uint128":
+                return { type: "integer", minimum: 0 };
+            case "date":
+            case "timestamp":
+                return { type: "string", format: "date-time" };
+            case "union":
+                return {
+                    anyOf: (schema._extraInfo as Schema<any>[]).map(
+                        (element) => this.convert(element),
+                    ),
+                };
+            case "taggedUnion": {
+                const variants = schema._extraInfo as ObjectSchemas;
+                return {
+                    oneOf: Object.keys(variants).map((tag) => ({
+                        type: "object",
+                        properties: {
+                            tag: { const: tag },
+                            value: this.convert(variants[tag]),
+                        },
+                        required: ["tag", "value"],
+                    })),
+                };
+            }
+            case "nullable":
+                if (!schema._extraInfo) {
+                    return { type: "null" };
+                }
+                return {
+                    anyOf: [this.convert(schema._extraInfo), { type: "null" }],
+                };
+            case "optional":
+                // Whether the key is required is handled by the object.
+                return this.convert(schema._extraInfo);
+            case "record":
+                return {
+                    type: "object",
+                    additionalProperties: this.convert(schema._extraInfo),
+                };
+            case "dynRecord": {
+                const [, valueSchema] = schema._extraInfo as [
+                    Schema<any>,
+                    Schema<any>,
+                ];
+                return {
+                    type: "object",
+                    additionalProperties: this.convert(valueSchema),
+                };
+            }
+            case "map": {
+                // Maps can have keys of any type, so they are represented as a list of pairs.
+                const [keySchema, valueSchema] = schema._extraInfo as [
+                    Schema<any>,
+                    Schema<any>,
+                ];
+                return {
+                    type: "array",
+                    items: {
+                        type: "array",
+                        prefixItems: [
+                            this.convert(keySchema),
+                            this.convert(valueSchema),
+                        ],
+                        minItems: 2,
+                        maxItems: 2,
+                    },
+                };
+            }
+            case "any":
+                return {};
+            default:
+                throw new Error(
+                    `Cannot convert schema of type ${schema.name} to JSON Schema`,
+                );
+        }
+    }
+}
+
+/**
+ * Converts a schema to a JSON Schema (draft 2020-12) document. This is useful for documentation
+ * and for working with tooling that already understands JSON Schema.
+ *
+ * Some types do not have an exact JSON equivalent, so they are approximated:
+ * - Binary data (Uint8Array, Buffer, and ReadableStream) is described as a base64 string.
+ * - Dates are described as date-time strings.
+ * - Bigints (including 128-bit and arbitrary size integers) are described as integers,
+ *   although JSON numbers can't hold every bigint.
+ * - Maps are described as an array of key value pairs.
+ * - Promises, compression tables, and pipe() schemas are described as their inner type.
+ * - Recursive schemas are put in $defs and referred to with $ref.
+ *
+ * Descriptions attached with annotate() are included in the output.
+ *
+ * @param schema - The schema to convert
+ * @returns The JSON Schema document
+ *
+ * @example
+ * ```typescript
+ * const userSchema = object({ name: string(), age: optional(uint()) });
+ * toJsonSchema(userSchema);
+ * // {
+ * //   $schema: "https://json-schema.org/draft/2020-12/schema",
+ * //   type: "object",
+ * //   properties: { age: { type: "integer", minimum: 0 }, name: { type: "string" } },
+ * //   required: ["name"],
+ * // }
+ * ```
+ */
+export function toJsonSchema(schema: Schema<any>): JsonSchema {
+    const converter = new Converter();
+    const res: JsonSchema = {
+        $schema: "https://json-schema.org/draft/2020-12/schema",
+        ...converter.convert(schema),
+    };
+    if (converter.defNames.size !== 0) res.$defs = converter.defs;
+    return res;
+}
@@ -0,0 +1,99 @@
+/**
+ * Options for {@link mergeValues}.
+ */
+export type MergeOptions = {
+    /**
+     * How to combine two arrays. "replace" (the default) uses the array from the later value,
+     * and "concat" appends the later array to the earlier one.
+     */
+    arrays?: "replace" | "concat";
+};
+
+function isPlainObject(value: unknown): value is { [key: string]: any } {
+    if (typeof value !== "object" || value === null) return false;
+    const proto = Object.getPrototypeOf(value);
+    return proto === Object.prototype || proto === null;
+}
+
+function kindOf(value: unknown) {
+    if (Array.isArray(value)) return "array";
+    if (value instanceof Map) return "map";
+    if (isPlainObject(value)) return "object";
+    return null;
+}
+
+function mergeInner(a: any, b: any, options: MergeOptions, path: string): any {
+    const aKind = kindOf(a);
+    const bKind = kindOf(b);
+
+    // Anything that isn't a container is simply replaced.
+    if (aKind === null || bKind === null) {
+        if (aKind !== bKind && a !== undefined && b !== undefined) {
+            throw new Error(
+                `Cannot merge ${bKind ?? typeof b} into ${aKind ?? typeof a} at ${path}`,
+            );
+        }
+        return b === undefined ? a : b;
+    }
+    if (aKind !== bKind) {
+        throw new Error(`Cannot merge ${bKind} into ${aKind} at ${path}`);
+    }
+
+    switch (aKind) {
+        case "array":
+            return options.arrays === "concat" ? [...a, ...b] : b;
+        case "map": {
+            const res = new Map(a);
+            for (const [key, value] of b) {
+                res.set(
+                    key,
+                    res.has(key)
+                        ? mergeInner(res.get(key), value, options, path)
+                        : value,
+                );
+            }
+            return res;
+        }
+        default: {
+            const res: any = { ...a };
+            for (const key of Object.keys(b)) {
+                res[key] = Object.prototype.hasOwnProperty.call(a, key)
+                    ? mergeInner(a[key], b[key], options, `${path}.${key}`)
+                    : b[key];
+            }
+            return res;
+        }
+    }
+}
+
+/**
+ * Deep merges two values, with keys from the later value overriding the earlier one. This is
+ * useful when assembling a value for an object or record schema from multiple sources.
+ *
+ * Objects and Maps are merged key by key, recursing into nested objects. Arrays are replaced by
+ * default, or concatenated if `arrays` is set to "concat". Any other value from the later value
+ * replaces the earlier one, and undefined values in the later value are ignored. Neither input is
+ * modified.
+ *
+ * @param a - The earlier value
+ * @param b - The later value, which takes priority
+ * @param options - Optional settings for how arrays are combined
+ * @returns The merged value
+ * @throws {Error} If a container is merged with a different kind of value, such as an object with an array
+ *
+ * @example
+ * ```typescript
+ * mergeValues(
+ *     { name: "John", address: { city: "London", zip: "E1" } },
+ *     { address: { zip: "N1" } },
+ * );
+ * // { name: "John", address: { city: "London", zip: "N1" } }
+ * ```
+ */
+export function mergeValues<T>(
+    a: T,
+    b: Partial<T> | T,
+    options: MergeOptions = {},
+): T {
+    return mergeInner(a, b, options, "$");
+}
@@ -0,0 +1,334 @@
+import { unionOptionIndex, type ObjectSchemas, type Schema } from "./schemas";
+import { typedArrayKinds } from "./utils";
+import type { output } from "./deserialize";
+
+function toBase64(bytes: Uint8Array) {
+    if (typeof Buffer !== "undefined") {
+        return Buffer.from(bytes).toString("base64");
+    }
+    let binary = "";
+    for (let i = 0; i < bytes.length; i++) {
+        binary += String.fromCharCode(bytes[i]);
+    }
+    return btoa(binary);
+}
+
+function fromBase64(text: string) {
+    if (typeof Buffer !== "undefined") {
+        return new Uint8Array(Buffer.from(text, "base64"));
+    }
+    const binary = atob(text);
+    const bytes = new Uint8Array(binary.length);
+    for (let i = 0; i < binary.length; i++) {
+        bytes[i] = binary.charCodeAt(i);
+    }
+    return bytes;
+}
+
+// Used for any() where the schema doesn't say what the value is.
+function renderUntyped(value: any): any {
+    if (typeof value === "bigint") return value.toString();
+    if (typeof value !== "object" || value === null) return value;
+    if (value instanceof Uint8Array) return { $bytes: toBase64(value) };
+    if (value instanceof Date) return value.toISOString();
+    if (value instanceof Map) {
+        return Array.from(value, ([k, v]) => [
+            renderUntyped(k),
+            renderUntyped(v),
+        ]);
+    }
+    if (Array.isArray(value)) return value.map(renderUntyped);
+    if (value instanceof Promise || value instanceof ReadableStream) {
+        return { $stream: true };
+    }
+    const res: any = {};
+    for (const key of Object.keys(value)) {
+        res[key] = renderUntyped(value[key]);
+    }
+    return res;
+}
+
+function render(schema: Schema<any>, value: any): any {
+    switch (schema.name) {
+        case "object":
+        case "orderedObject":
+        case "selfDescribingObject":
+        case "sparseObject":
+        case "partial": {
+            const schemas = schema._extraInfo as ObjectSchemas;
+            const res: any = {};
+            for (const key of Object.keys(schemas)) {
+                if (value[key] === undefined) continue;
+                res[key] = render(schemas[key], value[key]);
+            }
+            return res;
+        }
+        case "array":
+            return (value as any[]).map((item) =>
+                render(schema._extraInfo, item),
+            );
+        case "set":
+            return Array.from(value as Set<any>, (item) =>
+                render(schema._extraInfo, item),
+            );
+        case "tuple":
+            return (schema._extraInfo as Schema<any>[]).map((element, i) =>
+                render(element, value[i]),
+            );
+        case "typedArray":
+            return Array.from(value as ArrayLike<number>);
+        case "uint8array":
+        case "buffer":
+        case "fixedBytes":
+            return { $bytes: toBase64(value) };
+        case "date":
+        case "timestamp":
+            return (value as Date).toISOString();
+        case "bigint":
+        case "int128":
+        case "uint128":
+        case "arbitraryBigint":
+            return (value as bigint).toString();
+        case "union": {
+            const idx = unionOptionIndex(schema, value);
+            if (idx === undefined) return renderUntyped(value);
+            return render(schema._extraInfo[idx], value);
+        }
+        case "recursive":
+            return render(schema._extraInfo, value);
+        case "recursiveRef":
+            return render(schema._extraInfo.inner, value);
+        case "taggedUnion":
+            return {
+                tag: value.tag,
+                value: render(schema._extraInfo[value.tag], value.value),
+            };
+        case "nullable":
+            if (value === null || !schema._extraInfo) return null;
+            return render(schema._extraInfo, value);
+        case "optional":
+            // Leaves the field out, and null is passed on for the inner schema to render.
+            if (value === undefined) return undefined;
+            return render(schema._extraInfo, value);
+        case "compressionTable":
+            return render(schema._extraInfo, value);
+        case "record":
+        case "dynRecord": {
+            const valueSchema =
+                schema.name === "record"
+                    ? schema._extraInfo
+                    : schema._extraInfo[1];
+            const res: any = {};
+            for (const key of Object.keys(value)) {
+                res[key] = render(valueSchema, value[key]);
+            }
+            return res;
+        }
+        case "map": {
+            const [keySchema, valueSchema] = schema._extraInfo;
+            return Array.from(value as Map<any, any>, ([k, v]) => [
+                render(keySchema, k),
+                render(valueSchema, v),
+            ]);
+        }
+        case "promise":
+        case "iterator":
+        case "readableStream":
+            // These are still being received, so there is nothing to show yet.
+            return { $stream: schema.name };
+        case "any":
+            return renderUntyped(value);
+        default:
+            return value;
+    }
+}
+
+/**
+ * Renders a decoded value as indented JSON, using the schema to show types JSON can't represent.
+ * Binary data is written as `{ "$bytes": "<base64>" }`, dates as ISO strings, bigints (including
+ * 128-bit integers) as decimal strings, and Maps as arrays of `[key, value]` pairs.
+ *
+ * Streamed types are written as `{ "$stream": "<type>" }` since their data may not have arrived
+ * yet. Use resolveStreamedData() first to include it.
+ *
+ * @param schema - The schema the value was decoded with
+ * @param value - The decoded value
+ * @param indent - The number of spaces to indent by (default: 4)
+ * @returns The rendered JSON
+ *
+ * @example
+ * ```typescript
+ * const fileSchema = object({ data: uint8array(), modified: date() });
+ * toPrettyJson(fileSchema, { data: new Uint8Array([1, 2, 3]), modified: new Date(0) });
+ * // {
+ * //     "data": {
+ * //         "$bytes": "AQID"
+ * //     },
+ * //     "modified": "1970-01-01T00:00:00.000Z"
+ * // }
+ * ```
+ */
+export function toPrettyJson(
+    schema: Schema<any>,
+    value: any,
+    indent = 4,
+): string {
+    // An optional() value that is left out has nothing to be rendered as at the top level.
+    return JSON.stringify(render(schema, value) ?? null, null, indent);
+}
+
+// The inverse of renderUntyped(). Only bytes can be told apart from plain JSON, so dates, bigints,
+// and Maps stay as the strings and arrays they were rendered as.
+function parseUntyped(value: any): any {
+    if (typeof value !== "object" || value === null) return value;
+    if (Array.isArray(value)) return value.map(parseUntyped);
+    if (typeof value.$bytes === "string") return fromBase64(value.$bytes);
+    const res: any = {};
+    for (const key of Object.keys(value)) {
+        res[key] = parseUntyped(value[key]);
+    }
+    return res;
+}
+
+function parse(schema: Schema<any>, value: any): any {
+    switch (schema.name) {
+        case "object":
+        case "orderedObject":
+        case "selfDescribingObject":
+        case "sparseObject":
+        case "partial": {
+            const schemas = schema._extraInfo as ObjectSchemas;
+            const res: any = {};
+            for (const key of Object.keys(schemas)) {
+                if (value[key] === undefined) continue;
+                res[key] = parse(schemas[key], value[key]);
+            }
+            return res;
+        }
+        case "array":
+            return (value as any[]).map((item) =>
+                parse(schema._extraInfo, item),
+            );
+        case "set":
+            return new Set(
+                (value as any[]).map((item) => parse(schema._extraInfo, item)),
+            );
+        case "tuple":
+            return (schema._extraInfo as Schema<any>[]).map((element, i) =>
+                parse(element, value[i]),
+            );
+        case "typedArray": {
+            const kind = schema._extraInfo as keyof typeof typedArrayKinds;
+            return new typedArrayKinds[kind](value as number[]);
+        }
+        case "uint8array":
+        case "fixedBytes":
+            return fromBase64(value.$bytes);
+        case "buffer":
+            return Buffer.from(fromBase64(value.$bytes));
+        case "date":
+        case "timestamp":
+            return new Date(value);
+        case "bigint":
+        case "int128":
+        case "uint128":
+        case "arbitraryBigint":
+            return BigInt(value);
+        case "union": {
+            // JSON loses which option was used, so take the first one the value parses as.
+            for (const option of schema._extraInfo as Schema<any>[]) {
+                try {
+                    const res = parse(option, value);
+                    option.validateAndMakeWriter(res, {});
+                    return res;
+                } catch {
+                    // Try the next option.
+                }
+            }
+            return parseUntyped(value);
+        }
+        case "recursive":
+            return parse(schema._extraInfo, value);
+        case "recursiveRef":
+            return parse(schema._extraInfo.inner, value);
+        case "taggedUnion":
+            return {
+                tag: value.tag,
+                value: parse(schema._extraInfo[value.tag], value.value),
+            };
+        case "nullable":
+            if (value === null || !schema._extraInfo) return null;
+            return parse(schema._extraInfo, value);
+        case "optional":
+            if (value === undefined) return undefined;
+            if (value === null) {
+                // Arrays can't leave an item out, so JSON has null there instead. Only keep it
+                // if the inner schema allows null, like optional(nullable(x)).
+                try {
+                    schema._extraInfo.validateAndMakeWriter(null, {});
+                } catch {
+                    return undefined;
+                }
+            }
+            return parse(schema._extraInfo, value);
+        case "compressionTable":
+            return parse(schema._extraInfo, value);
+        case "record":
+        case "dynRecord": {
+            const valueSchema =
+                schema.name === "record"
+                    ? schema._extraInfo
+                    : schema._extraInfo[1];
+            const res: any = {};
+            for (const key of Object.keys(value)) {
+                res[key] = parse(valueSchema, value[key]);
+            }
+            return res;
+        }
+        case "map": {
+            const [keySchema, valueSchema] = schema._extraInfo;
+            return new Map(
+                (value as [any, any][]).map(([k, v]) => [
+                    parse(keySchema, k),
+                    parse(valueSchema, v),
+                ]),
+            );
+        }
+        case "promise":
+        case "iterator":
+        case "readableStream":
+            throw new Error(
+                `Streamed types can't be read from JSON (found ${schema.name})`,
+            );
+        case "any":
+            return parseUntyped(value);
+        default:
+            return value;
+    }
+}
+
+/**
+ * Turns JSON written by toPrettyJson() back into a value that can be serialized with the same
+ * schema. Bytes, dates, bigints, Sets, and Maps are rebuilt from how toPrettyJson() wrote them.
+ *
+ * Some things can't be recovered. Streamed types throw, since only a placeholder was written.
+ * Unions use the first option the value parses and validates as, and values under any() only
+ * get their bytes back, since nothing else says what they were.
+ *
+ * @param schema - The schema the value was rendered with
+ * @param json - The JSON text
+ * @returns The value
+ *
+ * @example
+ * ```typescript
+ * const fileSchema = object({ data: uint8array(), modified: date() });
+ * const json = toPrettyJson(fileSchema, { data: new Uint8Array([1]), modified: new Date(0) });
+ * fromPrettyJson(fileSchema, json); // { data: Uint8Array [1], modified: Date(0) }
+ * ```
+ */
+export function fromPrettyJson<S extends Schema<any>>(
+    schema: S,
+    json: string,
+): output<S> {
+    return parse(schema, JSON.parse(json));
+}
@@ -1,67 +1,173 @@
 import {
     any,
+    arbitraryBigint,
     array,
     bigint,
     boolean,
+    buildInside,
+    booleanArray,
     buffer,
     compressionTable,
     date,
+    dynRecord,
+    finishRecursive,
+    fixedBytes,
     float,
+    float32,
     int,
+    int128,
     iterator,
     map,
     nullable,
     object,
     optional,
+    orderedObject,
+    partial,
     potentiallyFloatString,
     promise,
     readableStream,
     record,
+    recursiveRef,
+    selfDescribingObject,
+    set,
+    sparseObject,
     string,
+    taggedUnion,
+    timestamp,
+    tuple,
+    typedArray,
     uint,
+    uint128,
     uint8,
     uint8array,
     union,
+    type RecursiveDefinition,
     type Schema,
 } from "./schemas";
-import { dataType, readRollingUintNoAlloc } from "./utils";
-import type { ReadContext } from "./ReadContext";
+import {
+    dataType,
+    readRollingUintNoAlloc,
+    typedArrayKindIds,
+} from "./utils";
+import { DataError, type ReadContext } from "./ReadContext";
 
 const td = new TextDecoder();
 
+// The recursive definitions each context is inside of, innermost last.
+const recursionStacks = new WeakMap<ReadContext, RecursiveDefinition[]>();
+
+// The schemas read so far, numbered in the order they started. A schema is only added once
+// it has been read in full, so a reference can't point at one that contains it.
+type ReadSchemas = (Schema<any> | undefined)[];
+
+async function reflectObjectFields(ctx: ReadContext, read: ReadSchemas) {
+    const numFields = await readRollingUintNoAlloc(ctx);
+    const fields: Record<string, Schema<any>> = {};
+    for (let i = 0; i < numFields; i++) {
+        const start = ctx.position;
+        const fieldNameLength = await readRollingUintNoAlloc(ctx);
+        const fieldNameBytes = await ctx.readBytes(fieldNameLength);
+        const fieldName = td.decode(fieldNameBytes);
+        if (fieldName === "prototype" || fieldName === "__proto__") {
+            throw new DataError(
+                "Reflected schema contains invalid field name",
+                start,
+            );
+        }
+        fields[fieldName] = await reflectSchema(ctx, read);
+    }
+    return fields;
+}
+
 export async function reflectByteReprToSchema(
     ctx: ReadContext,
+    enclosing: RecursiveDefinition[] = [],
+): Promise<Schema<any>> {
+    if (enclosing.length === 0) return reflectSchema(ctx, []);
+
+    // References in the schema can point at the recursive definitions it was written inside of.
+    let stack = recursionStacks.get(ctx);
+    if (!stack) {
+        stack = [];
+        recursionStacks.set(ctx, stack);
+    }
+    const before = stack.length;
+    stack.push(...enclosing);
+    try {
+        return await reflectSchema(ctx, []);
+    } finally {
+        stack.length = before;
+    }
+}
+
+async function reflectSchema(
+    ctx: ReadContext,
+    read: ReadSchemas,
+): Promise<Schema<any>> {
+    // Deeply nested schemas are cheap to send but expensive to build, so limit them.
+    if (ctx.depth >= ctx.maxDepth) {
+        throw new DataError(
+            `Reflected schema is nested more than ${ctx.maxDepth} levels deep`,
+            ctx.position,
+        );
+    }
+    if ((await ctx.peekByte()) === dataType.schemaRef) {
+        const start = ctx.position;
+        await ctx.readByte();
+        const number = await readRollingUintNoAlloc(ctx);
+        const schema = read[number];
+        if (!schema) {
+            throw new DataError(
+                `Schema reference ${number} refers to a schema that hasn't been read yet`,
+                start,
+            );
+        }
+        return schema;
+    }
+
+    const number = read.length;
+    read.push(undefined);
+    ctx.depth++;
+    try {
+        const schema = await reflectType(ctx, read);
+        read[number] = schema;
+        return schema;
+    } finally {
+        ctx.depth--;
+    }
+}
+
+async function reflectType(
+    ctx: ReadContext,
+    read: ReadSchemas,
 ): Promise<Schema<any>> {
     const typeByte = await ctx.readByte();
     switch (typeByte) {
         case dataType.array:
-            return array(await reflectByteReprToSchema(ctx));
+            return array(await reflectSchema(ctx, read));
+        case dataType.set:
+            return set(await reflectSchema(ctx, read));
         case dataType.boolean:
             return boolean();
+        case dataType.booleanArray:
+            return booleanArray();
         case dataType.u8array:
             return uint8array();
         case dataType.buffer:
             return buffer();
         case dataType.iterator:
-            return iterator(await reflectByteReprToSchema(ctx));
-        case dataType.object: {
-            const numFields = await readRollingUintNoAlloc(ctx);
-            const fields: Record<string, Schema<any>> = {};
-            for (let i = 0; i < numFields; i++) {
-                const fieldNameLength = await readRollingUintNoAlloc(ctx);
-                const fieldNameBytes = await ctx.readBytes(fieldNameLength);
-                const fieldName = td.decode(fieldNameBytes);
-                if (fieldName === "prototype" || fieldName === "__proto__") {
-                    throw new Error(
-                        "Reflected schema contains invalid field name",
-                    );
-                }
-                fields[fieldName] = await reflectByteReprToSchema(ctx);
-            }
-            return object(fields);
-        }
+            return iterator(await reflectSchema(ctx, read));
+        case dataType.object:
+            return object(await reflectObjectFields(ctx, read));
+        case dataType.orderedObject:
+            // Fields are read in the order they were written, which is kept
+            return orderedObject(await reflectObjectFields(ctx, read));
+        case dataType.sparseObject:
+            return sparseObject(await reflectObjectFields(ctx, read));
+        case dataType.partial:
+            return partial(object(await reflectObjectFields(ctx, read)));
         case dataType.promise:
-            return promise(await reflectByteReprToSchema(ctx));
+            return promise(await reflectSchema(ctx, read));
         case dataType.string:
             return string();
         case dataType.uint8:
@@ -72,47 +178,121 @@ export async function reflectByteReprToSchema(
             const numOptions = (await readRollingUintNoAlloc(ctx)) + 1;
             const options: Schema<any>[] = [];
             for (let i = 0; i < numOptions; i++) {
-                options.push(await reflectByteReprToSchema(ctx));
+                options.push(await reflectSchema(ctx, read));
             }
             return union(options.shift()!, ...options);
         }
         case dataType.date:
             return date();
+        case dataType.timestamp:
+            return timestamp();
         case dataType.int:
             return int();
         case dataType.float:
             return float();
+        case dataType.float32:
+            return float32();
         case dataType.nullable: {
             const next = await ctx.peekByte();
             if (next === 0x00) {
-                // No child
+                // No child, consume the marker so it isn't read as the next schema
+                await ctx.readByte();
                 return nullable();
             }
-            return nullable(await reflectByteReprToSchema(ctx));
+            return nullable(await reflectSchema(ctx, read));
         }
         case dataType.optional:
-            return optional(await reflectByteReprToSchema(ctx));
+            return optional(await reflectSchema(ctx, read));
         case dataType.bigint:
             return bigint();
+        case dataType.arbitraryBigint:
+            return arbitraryBigint();
+        case dataType.int128:
+            return int128();
+        case dataType.uint128:
+            return uint128();
         case dataType.readableStream:
             return readableStream();
         case dataType.record:
-            return record(await reflectByteReprToSchema(ctx));
+            return record(await reflectSchema(ctx, read));
+        case dataType.dynRecord:
+            return dynRecord(
+                await reflectSchema(ctx, read),
+                await reflectSchema(ctx, read),
+            );
         case dataType.map:
             return map(
-                await reflectByteReprToSchema(ctx),
-                await reflectByteReprToSchema(ctx),
+                await reflectSchema(ctx, read),
+                await reflectSchema(ctx, read),
             );
         case dataType.any:
             return any();
         case dataType.compressionTable:
             // deep doesn't matter for read reflection
-            return compressionTable(await reflectByteReprToSchema(ctx), false);
+            return compressionTable(await reflectSchema(ctx, read), false);
         case dataType.potentiallyFloatString:
             return potentiallyFloatString();
+        case dataType.selfDescribingObject:
+            // The fields are described on the wire, so keep everything we read
+            return buildInside(recursionStacks.get(ctx) ?? [], () =>
+                selfDescribingObject({}, undefined, true),
+            );
+        case dataType.typedArray: {
+            const kindId = await ctx.readByte();
+            const kind = typedArrayKindIds[kindId];
+            if (!kind) {
+                throw new DataError(
+                    `Unknown typed array kind in reflected schema: ${kindId}`,
+                    ctx.position - 1,
+                );
+            }
+            return typedArray(kind);
+        }
+        case dataType.fixedBytes:
+            return fixedBytes(await readRollingUintNoAlloc(ctx));
+        case dataType.taggedUnion:
+            return taggedUnion(await reflectObjectFields(ctx, read));
+        case dataType.recursive: {
+            let stack = recursionStacks.get(ctx);
+            if (!stack) {
+                stack = [];
+                recursionStacks.set(ctx, stack);
+            }
+            const definition: RecursiveDefinition = { inner: undefined };
+            stack.push(definition);
+            let inner: Schema<any>;
+            try {
+                inner = await reflectSchema(ctx, read);
+            } finally {
+                stack.pop();
+            }
+            return finishRecursive(definition, inner);
+        }
+        case dataType.recursiveRef: {
+            const start = ctx.position;
+            const depth = await readRollingUintNoAlloc(ctx);
+            const stack = recursionStacks.get(ctx);
+            const definition = stack?.[stack.length - 1 - depth];
+            if (!definition) {
+                throw new DataError(
+                    `Recursive reference ${depth} levels out is not inside a recursive schema`,
+                    start,
+                );
+            }
+            return recursiveRef(definition, depth);
+        }
+        case dataType.tuple: {
+            const len = await readRollingUintNoAlloc(ctx);
+            const elements: Schema<any>[] = [];
+            for (let i = 0; i < len; i++) {
+                elements.push(await reflectSchema(ctx, read));
+            }
+            return tuple(...elements);
+        }
         default:
-            throw new Error(
+            throw new DataError(
                 `Unknown type byte in reflected schema: ${typeByte}`,
+                ctx.position - 1,
             );
     }
 }
@@ -0,0 +1,302 @@
+import {
+    array,
+    compressionTable,
+    dynRecord,
+    map,
+    nullable,
+    object,
+    optional,
+    orderedObject,
+    partial,
+    record,
+    recursive,
+    selfDescribingObject,
+    set,
+    sparseObject,
+    taggedUnion,
+    tuple,
+    uint8array,
+    union,
+    unionOptionIndex,
+    type ObjectSchemas,
+    type Schema,
+} from "./schemas";
+
+// The recursive() schemas being rebuilt right now, from the old inner schema to a reference to
+// the new one.
+const rebuilding = new Map<Schema<any>, Schema<any>>();
+
+function resolveObjectSchemas(schemas: ObjectSchemas): ObjectSchemas | null {
+    let changed = false;
+    const res: ObjectSchemas = {};
+    for (const key of Object.keys(schemas)) {
+        res[key] = resolveStreams(schemas[key]);
+        if (res[key] !== schemas[key]) changed = true;
+    }
+    return changed ? res : null;
+}
+
+function resolveSchemaList(schemas: Schema<any>[]): Schema<any>[] | null {
+    const res = schemas.map(resolveStreams);
+    return res.some((s, i) => s !== schemas[i]) ? res : null;
+}
+
+/**
+ * Turns a schema into one without any streamed types, so that data can be written in one go.
+ * Promises are replaced with the type they resolve to, iterators with arrays, and readable streams
+ * with Uint8Arrays. Use {@link resolveStreamedData} to turn data for the original schema into data
+ * for the new one.
+ *
+ * Parts of the schema that don't contain streamed types are kept as is. Parts that do are rebuilt,
+ * which means any custom validation messages inside of them are lost.
+ *
+ * @param schema - The schema to resolve
+ * @returns The schema with all streamed types replaced
+ *
+ * @example
+ * ```typescript
+ * const userSchema = object({ name: string(), friends: promise(array(string())) });
+ * const resolved = resolveStreams(userSchema); // object({ name: string(), friends: array(string()) })
+ * ```
+ */
+export function resolveStreams(schema: Schema<any>): Schema<any> {
+    switch (schema.name) {
+        case "promise":
+            return resolveStreams(schema._extraInfo);
+        case "iterator":
+            return array(resolveStreams(schema._extraInfo));
+        case "readableStream":
+            return uint8array();
+        case "array": {
+            const inner = resolveStreams(schema._extraInfo);
+            return inner === schema._extraInfo ? schema : array(inner);
+        }
+        case "set": {
+            const inner = resolveStreams(schema._extraInfo);
+            return inner === schema._extraInfo ? schema : set(inner);
+        }
+        case "object": {
+            const fields = resolveObjectSchemas(schema._extraInfo);
+            return fields ? object(fields) : schema;
+        }
+        case "orderedObject": {
+            const fields = resolveObjectSchemas(schema._extraInfo);
+            return fields ? orderedObject(fields) : schema;
+        }
+        case "selfDescribingObject": {
+            const fields = resolveObjectSchemas(schema._extraInfo);
+            return fields ? selfDescribingObject(fields) : schema;
+        }
+        case "sparseObject": {
+            const fields = resolveObjectSchemas(schema._extraInfo);
+            return fields ? sparseObject(fields) : schema;
+        }
+        case "tuple": {
+            const elements = resolveSchemaList(schema._extraInfo);
+            return elements ? tuple(...elements) : schema;
+        }
+        case "partial": {
+            const fields = resolveObjectSchemas(schema._extraInfo);
+            return fields ? partial(object(fields)) : schema;
+        }
+        case "union": {
+            const options = resolveSchemaList(schema._extraInfo);
+            return options ? union(options[0], ...options.slice(1)) : schema;
+        }
+        case "taggedUnion": {
+            const variants = resolveObjectSchemas(schema._extraInfo);
+            return variants ? taggedUnion(variants) : schema;
+        }
+        case "nullable": {
+            if (!schema._extraInfo) return schema;
+            const inner = resolveStreams(schema._extraInfo);
+            return inner === schema._extraInfo ? schema : nullable(inner);
+        }
+        case "optional": {
+            const inner = resolveStreams(schema._extraInfo);
+            return inner === schema._extraInfo ? schema : optional(inner);
+        }
+        case "record": {
+            const inner = resolveStreams(schema._extraInfo);
+            return inner === schema._extraInfo ? schema : record(inner);
+        }
+        case "dynRecord":
+        case "map": {
+            const pair = resolveSchemaList(schema._extraInfo);
+            if (!pair) return schema;
+            return schema.name === "map"
+                ? map(pair[0], pair[1])
+                : dynRecord(pair[0], pair[1]);
+        }
+        case "compressionTable": {
+            const inner = resolveStreams(schema._extraInfo);
+            // Deep checks only matter for streamed types, which are now gone
+            return inner === schema._extraInfo
+                ? schema
+                : compressionTable(inner, false);
+        }
+        case "recursive": {
+            const inner = schema._extraInfo as Schema<any>;
+            // References are left as they are on this pass, so nothing is rebuilt unless there
+            // are streamed types inside.
+            if (resolveStreams(inner) === inner) return schema;
+            return recursive((self) => {
+                rebuilding.set(inner, self);
+                try {
+                    return resolveStreams(inner);
+                } finally {
+                    rebuilding.delete(inner);
+                }
+            });
+        }
+        case "recursiveRef":
+            return rebuilding.get(schema._extraInfo.inner) ?? schema;
+        default:
+            return schema;
+    }
+}
+
+async function readAll(stream: ReadableStream<Uint8Array>) {
+    const chunks: Uint8Array[] = [];
+    let len = 0;
+    const reader = stream.getReader();
+    for (;;) {
+        const { done, value } = await reader.read();
+        if (done) break;
+        chunks.push(value);
+        len += value.length;
+    }
+    const res = new Uint8Array(len);
+    let pos = 0;
+    for (const chunk of chunks) {
+        res.set(chunk, pos);
+        pos += chunk.length;
+    }
+    return res;
+}
+
+/**
+ * Waits for all the streamed parts of data to finish, so that the result can be written with the
+ * schema returned by {@link resolveStreams}. Promises are awaited, iterators are collected into
+ * arrays, and readable streams are read into a single Uint8Array.
+ *
+ * @param schema - The original schema the data matches
+ * @param data - The data to resolve
+ * @returns Promise resolving to the data with all streamed parts resolved
+ *
+ * @example
+ * ```typescript
+ * const resolved = await resolveStreamedData(userSchema, {
+ *   name: "John",
+ *   friends: fetchFriends(),
+ * });
+ * const buf = await serializeToBuffer(resolveStreams(userSchema), resolved);
+ * ```
+ */
+export async function resolveStreamedData(
+    schema: Schema<any>,
+    data: any,
+): Promise<any> {
+    switch (schema.name) {
+        case "promise":
+            return resolveStreamedData(schema._extraInfo, await data);
+        case "iterator": {
+            const res: any[] = [];
+            for await (const item of data) {
+                res.push(await resolveStreamedData(schema._extraInfo, item));
+            }
+            return res;
+        }
+        case "readableStream":
+            return readAll(data);
+        case "compressionTable":
+        case "recursive":
+            return resolveStreamedData(schema._extraInfo, data);
+        case "recursiveRef":
+            return resolveStreamedData(schema._extraInfo.inner, data);
+        case "array":
+            return Promise.all(
+                (data as any[]).map((item) =>
+                    resolveStreamedData(schema._extraInfo, item),
+                ),
+            );
+        case "set":
+            return new Set(
+                await Promise.all(
+                    Array.from(data as Set<any>, (item) =>
+                        resolveStreamedData(schema._extraInfo, item),
+                    ),
+                ),
+            );
+        case "tuple":
+            return Promise.all(
+                (schema._extraInfo as Schema<any>[]).map((element, i) =>
+                    resolveStreamedData(element, data[i]),
+                ),
+            );
+        case "object":
+        case "orderedObject":
+        case "selfDescribingObject":
+        case "sparseObject": {
+            const schemas = schema._extraInfo as ObjectSchemas;
+            const res: any = { ...data };
+            for (const key of Object.keys(schemas)) {
+                res[key] = await resolveStreamedData(schemas[key], data[key]);
+            }
+            return res;
+        }
+        case "partial": {
+            const schemas = schema._extraInfo as ObjectSchemas;
+            const res: any = { ...data };
+            for (const key of Object.keys(schemas)) {
+                if (data[key] === undefined) continue;
+                res[key] = await resolveStreamedData(schemas[key], data[key]);
+            }
+            return res;
+        }
+        case "union": {
+            const idx = unionOptionIndex(schema, data);
+            if (idx === undefined) return data;
+            return resolveStreamedData(schema._extraInfo[idx], data);
+        }
+        case "taggedUnion":
+            return {
+                tag: data.tag,
+                value: await resolveStreamedData(
+                    schema._extraInfo[data.tag],
+                    data.value,
+                ),
+            };
+        case "nullable":
+        case "optional":
+            if (data === null || data === undefined || !schema._extraInfo) {
+                return data;
+            }
+            return resolveStreamedData(schema._extraInfo, data);
+        case "record":
+        case "dynRecord": {
+            const valueSchema =
+                schema.name === "record"
+                    ? schema._extraInfo
+                    : schema._extraInfo[1];
+            const res: any = {};
+            for (const key of Object.keys(data)) {
+                res[key] = await resolveStreamedData(valueSchema, data[key]);
+            }
+            return res;
+        }
+        case "map": {
+            const [keySchema, valueSchema] = schema._extraInfo;
+            const res = new Map();
+            for (const [key, value] of data) {
+                res.set(
+                    await resolveStreamedData(keySchema, key),
+                    await resolveStreamedData(valueSchema, value),
+                );
+            }
+            return res;
+        }
+        default:
+            return data;
+    }
+}
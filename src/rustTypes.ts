@@ -0,0 +1,312 @@
+import type { ObjectSchemas, Schema } from "./schemas";
+
+const derives =
+    "#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]";
+
+// prettier-ignore
+const rustKeywords = new Set([
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else",
+    "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match",
+    "mod", "move", "mut", "pub", "ref", "return", "self", "static", "struct", "super",
+    "trait", "true", "type", "unsafe", "use", "where", "while", "yield",
+]);
+
+function words(name: string) {
+    return name
+        .replace(/([a-z0-9])([A-Z])/g, "$1 $2")
+        .split(/[^A-Za-z0-9]+/)
+        .filter((w) => w !== "");
+}
+
+function pascalCase(name: string) {
+    const res = words(name)
+        .map((w) => w[0].toUpperCase() + w.slice(1).toLowerCase())
+        .join("");
+    return /^[0-9]/.test(res) || res === "" ? `T${res}` : res;
+}
+
+function snakeCase(name: string) {
+    let res = words(name)
+        .map((w) => w.toLowerCase())
+        .join("_");
+    if (/^[0-9]/.test(res) || res === "") res = `_${res}`;
+    return rustKeywords.has(res) ? `r#${res}` : res;
+}
+
+const typedArrayElements: { [kind: string]: string } = {
+    int8: "i8",
+    int16: "i16",
+    uint16: "u16",
+    int32: "i32",
+    uint32: "u32",
+    float32: "f32",
+    float64: "f64",
+};
+
+class Generator {
+    private _definitions: string[] = [];
+    private _names = new Set<string>();
+    private _objects = new Map<ObjectSchemas, string>();
+    private _recursive = new Map<Schema<any>, string>();
+
+    private _reserve(name: string) {
+        let res = name;
+        for (let i = 2; this._names.has(res); i++) {
+            res = `${name}${i}`;
+        }
+        this._names.add(res);
+        return res;
+    }
+
+    private _struct(schema: Schema<any>, name: string) {
+        const schemas = schema._extraInfo as ObjectSchemas;
+        const existing = this._objects.get(schemas);
+        if (existing) return existing;
+
+        const structName = this._reserve(name);
+        this._objects.set(schemas, structName);
+
+        // Every field is left out when it isn't set in a partial object.
+        const allOptional = schema.name === "partial";
+        const keys = Object.keys(schemas);
+        if (schema.name !== "orderedObject") {
+            keys.sort((a, b) => a.localeCompare(b));
+        }
+
+        const lines = [derives, `pub struct ${structName} {`];
+        for (const key of keys) {
+            let fieldType = this.typeOf(
+                schemas[key],
+                structName + pascalCase(key),
+            );
+            const isOptional = allOptional || schemas[key].name === "optional";
+            if (allOptional && schemas[key].name !== "optional") {
+                fieldType = `Option<${fieldType}>`;
+            }
+
+            const fieldName = snakeCase(key);
+            if (fieldName.replace(/^r#/, "") !== key) {
+                lines.push(`    #[serde(rename = ${JSON.stringify(key)})]`);
+            }
+            if (isOptional) {
+                lines.push(
+                    '    #[serde(default, skip_serializing_if = "Option::is_none")]',
+                );
+            }
+            lines.push(`    pub ${fieldName}: ${fieldType},`);
+        }
+        lines.push("}");
+        this._definitions.push(lines.join("\n"));
+        return structName;
+    }
+
+    private _enum(options: Schema<any>[], name: string) {
+        const enumName = this._reserve(name);
+        const variantNames = new Set<string>();
+        const lines = [derives, "#[serde(untagged)]", `pub enum ${enumName} {`];
+        for (const option of options) {
+            let variant = pascalCase(option.name);
+            for (let i = 2; variantNames.has(variant); i++) {
+                variant = `${pascalCase(option.name)}${i}`;
+            }
+            variantNames.add(variant);
+            const innerType = this.typeOf(option, enumName + variant);
+            lines.push(`    ${variant}(${innerType}),`);
+        }
+        lines.push("}");
+        this._definitions.push(lines.join("\n"));
+        return enumName;
+    }
+
+    private _taggedEnum(variants: ObjectSchemas, name: string) {
+        const enumName = this._reserve(name);
+        const lines = [
+            derives,
+            '#[serde(tag = "tag", content = "value")]',
+            `pub enum ${enumName} {`,
+        ];
+        for (const tag of Object.keys(variants)) {
+            const variant = pascalCase(tag);
+            const innerType = this.typeOf(variants[tag], enumName + variant);
+            if (variant !== tag) {
+                lines.push(`    #[serde(rename = ${JSON.stringify(tag)})]`);
+            }
+            lines.push(`    ${variant}(${innerType}),`);
+        }
+        lines.push("}");
+        this._definitions.push(lines.join("\n"));
+        return enumName;
+    }
+
+    private _recursiveType(inner: Schema<any>, name: string) {
+        const existing = this._recursive.get(inner);
+        if (existing) return existing;
+
+        // Structs and enums take the first name reserved while making them, so freeing the name
+        // lets the type the references point at have it.
+        const typeName = this._reserve(name);
+        this._recursive.set(inner, typeName);
+        this._names.delete(typeName);
+        const innerType = this.typeOf(inner, typeName);
+        if (innerType !== typeName) {
+            this._names.add(typeName);
+            this._definitions.push(`pub type ${typeName} = ${innerType};`);
+        }
+        return typeName;
+    }
+
+    typeOf(schema: Schema<any>, name: string): string {
+        switch (schema.name) {
+            case "object":
+            case "orderedObject":
+            case "selfDescribingObject":
+            case "sparseObject":
+            case "partial":
+                return this._struct(schema, name);
+            case "array":
+            case "set":
+            case "iterator": {
+                const itemType = this.typeOf(schema._extraInfo, name + "Item");
+                return `Vec<${itemType}>`;
+            }
+            case "tuple": {
+                const elements = (schema._extraInfo as Schema<any>[]).map(
+                    (element, i) => this.typeOf(element, `${name}${i}`),
+                );
+                return `(${elements.join(", ")}${elements.length === 1 ? "," : ""})`;
+            }
+            case "booleanArray":
+                return "Vec<bool>";
+            case "typedArray":
+                return `Vec<${typedArrayElements[schema._extraInfo]}>`;
+            case "string":
+            case "potentiallyFloatString":
+                return "String";
+            case "date":
+                // Dates are sent as ISO 8601 strings.
+                return "String";
+            case "timestamp":
+                // Milliseconds since the Unix epoch.
+                return "i64";
+            case "fixedBytes":
+                return `[u8; ${schema._extraInfo}]`;
+            case "uint8array":
+            case "buffer":
+            case "readableStream":
+                return "Vec<u8>";
+            case "boolean":
+                return "bool";
+            case "uint8":
+                return "u8";
+            case "uint":
+                return "u64";
+            case "int":
+                return "i64";
+            case "float":
+                return "f64";
+            case "float32":
+                return "f32";
+            case "bigint":
+                return "u64";
+            case "int128":
+                return "i128";
+            case "uint128":
+                return "u128";
+            case "arbitraryBigint":
+                return "num_bigint::BigInt";
+            case "promise":
+            case "compressionTable":
+                return this.typeOf(schema._extraInfo, name);
+            case "nullable":
+                if (!schema._extraInfo) return "()";
+                return `Option<${this.typeOf(schema._extraInfo, name)}>`;
+            case "optional":
+                return `Option<${this.typeOf(schema._extraInfo, name)}>`;
+            case "union":
+                return this._enum(schema._extraInfo, name);
+            case "taggedUnion":
+                return this._taggedEnum(schema._extraInfo, name);
+            case "record": {
+                const valueType = this.typeOf(
+                    schema._extraInfo,
+                    name + "Value",
+                );
+                return `std::collections::HashMap<String, ${valueType}>`;
+            }
+            case "dynRecord":
+            case "map": {
+                const [keySchema, valueSchema] = schema._extraInfo as [
+                    Schema<any>,
+                    Schema<any>,
+                ];
+                const keyType = this.typeOf(keySchema, name + "Key");
+                const valueType = this.typeOf(valueSchema, name + "Value");
+                return `std::collections::HashMap<${keyType}, ${valueType}>`;
+            }
+            case "recursive":
+                return this._recursiveType(schema._extraInfo, name);
+            case "recursiveRef": {
+                const typeName = this._recursive.get(schema._extraInfo.inner);
+                if (!typeName) {
+                    throw new Error(
+                        "Cannot convert a recursive reference outside of its recursive schema",
+                    );
+                }
+                // Boxed, since a type can't contain itself directly.
+                return `Box<${typeName}>`;
+            }
+            case "any":
+                return "serde_json::Value";
+            default:
+                throw new Error(
+                    `Cannot convert schema of type ${schema.name} to a Rust type`,
+                );
+        }
+    }
+
+    output(rootName: string, rootType: string) {
+        if (rootType !== rootName) {
+            // The root isn't a struct or enum, so give it a name with an alias.
+            this._definitions.push(`pub type ${rootName} = ${rootType};`);
+        }
+        return this._definitions.join("\n\n") + "\n";
+    }
+}
+
+/**
+ * Generates Rust type definitions for a schema, for sharing schemas with Rust services. Objects
+ * become structs, with nested objects getting their own structs named after the path to them,
+ * unions become untagged enums, and tagged unions become enums tagged with tag and value.
+ * Everything derives serde's Serialize and Deserialize, so the types can be used with a serde
+ * based reader on the Rust side.
+ *
+ * Field names are converted to snake case and renamed back with serde where they differ. Dates
+ * are represented as ISO 8601 strings, and streamed types are represented as the data they
+ * resolve to. Recursive schemas refer back to their own type through a Box.
+ *
+ * @param schema - The schema to generate types for
+ * @param typeName - The name of the root type
+ * @returns The Rust source code
+ * @throws {Error} If the schema contains a type that can't be represented in Rust
+ *
+ * @example
+ * ```typescript
+ * toRustTypes(object({ name: string(), homeAddress: object({ city: string() }) }), "User");
+ * // #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+ * // pub struct UserHomeAddress {
+ * //     pub city: String,
+ * // }
+ * //
+ * // #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+ * // pub struct User {
+ * //     #[serde(rename = "homeAddress")]
+ * //     pub home_address: UserHomeAddress,
+ * //     pub name: String,
+ * // }
+ * ```
+ */
+export function toRustTypes(schema: Schema<any>, typeName: string): string {
+    const generator = new Generator();
+    const rootName = pascalCase(typeName);
+    return generator.output(rootName, generator.typeOf(schema, rootName));
+}
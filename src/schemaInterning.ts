@@ -0,0 +1,169 @@
+import {
+    dataType,
+    getRollingUintSize,
+    writeRollingUintNoAlloc,
+} from "./utils";
+
+// A schema inside the byte representation, and the schemas directly inside of it.
+type SchemaNode = {
+    start: number;
+    end: number;
+    children: SchemaNode[];
+
+    // How many recursive() levels outside of this schema its references point to. Schemas
+    // that point outside of themselves mean something different elsewhere, so aren't shared.
+    escapes: number;
+};
+
+// These are schema bytes we made ourselves, so they are always well formed.
+function readRollingUint(bytes: Uint8Array, pos: number): [number, number] {
+    const first = bytes[pos];
+    if (first < 0xfd) return [first, pos + 1];
+    const size = first === 0xfd ? 2 : first === 0xfe ? 4 : 8;
+    let value = 0;
+    for (let i = size; i > 0; i--) {
+        value = value * 256 + bytes[pos + i];
+    }
+    return [value, pos + 1 + size];
+}
+
+function parseNode(bytes: Uint8Array, start: number): SchemaNode {
+    const children: SchemaNode[] = [];
+    let pos = start + 1;
+    let escapes = 0;
+    const child = () => {
+        const node = parseNode(bytes, pos);
+        children.push(node);
+        pos = node.end;
+    };
+
+    switch (bytes[start]) {
+        case dataType.array:
+        case dataType.set:
+        case dataType.iterator:
+        case dataType.promise:
+        case dataType.optional:
+        case dataType.record:
+        case dataType.compressionTable:
+        case dataType.recursive:
+            child();
+            break;
+        case dataType.nullable:
+            if (bytes[pos] === 0x00) pos++;
+            else child();
+            break;
+        case dataType.map:
+        case dataType.dynRecord:
+            child();
+            child();
+            break;
+        case dataType.object:
+        case dataType.orderedObject:
+        case dataType.sparseObject:
+        case dataType.partial:
+        case dataType.taggedUnion: {
+            let count: number;
+            [count, pos] = readRollingUint(bytes, pos);
+            for (let i = 0; i < count; i++) {
+                let keyLen: number;
+                [keyLen, pos] = readRollingUint(bytes, pos);
+                pos += keyLen;
+                child();
+            }
+            break;
+        }
+        case dataType.union:
+        case dataType.tuple: {
+            let count: number;
+            [count, pos] = readRollingUint(bytes, pos);
+            // Unions write one less than the number of options.
+            if (bytes[start] === dataType.union) count++;
+            for (let i = 0; i < count; i++) child();
+            break;
+        }
+        case dataType.typedArray:
+            pos++;
+            break;
+        case dataType.fixedBytes:
+            [, pos] = readRollingUint(bytes, pos);
+            break;
+        case dataType.recursiveRef: {
+            let depth: number;
+            [depth, pos] = readRollingUint(bytes, pos);
+            escapes = depth + 1;
+            break;
+        }
+    }
+
+    for (const node of children) {
+        escapes = Math.max(escapes, node.escapes);
+    }
+    if (bytes[start] === dataType.recursive) {
+        // References to this level are inside of it.
+        escapes = Math.max(0, escapes - 1);
+    }
+    return { start, end: pos, children, escapes };
+}
+
+/**
+ * Rewrites a schema's byte representation so that each sub-schema that appears more than once
+ * is written in full the first time, and as a back-reference after that. Every schema written
+ * in full is numbered in the order it starts, and a back-reference is the schemaRef type byte
+ * followed by that number as a rolling uint.
+ *
+ * This is only used in headers, so the schema's own bytes and hash stay the same.
+ *
+ * @param schema - The byte representation of the schema
+ * @returns The interned byte representation
+ */
+export function internSchemaBytes(schema: Uint8Array): Uint8Array {
+    const parts: Uint8Array[] = [];
+    let size = 0;
+    const push = (part: Uint8Array) => {
+        parts.push(part);
+        size += part.length;
+    };
+
+    const numbers = new Map<string, number>();
+    let count = 0;
+    const write = (node: SchemaNode) => {
+        const bytes = schema.subarray(node.start, node.end);
+        let key = "";
+        for (let i = 0; i < bytes.length; i++) {
+            key += String.fromCharCode(bytes[i]);
+        }
+
+        const number = numbers.get(key);
+        if (number !== undefined) {
+            const refSize = 1 + getRollingUintSize(number);
+            if (refSize < bytes.length) {
+                const ref = new Uint8Array(refSize);
+                ref[0] = dataType.schemaRef;
+                writeRollingUintNoAlloc(number, ref, 1);
+                push(ref);
+                return;
+            }
+        } else if (node.escapes === 0) {
+            numbers.set(key, count);
+        }
+
+        // The reader numbers every schema written in full, even ones never referred to.
+        count++;
+        let pos = node.start;
+        for (const child of node.children) {
+            push(schema.subarray(pos, child.start));
+            write(child);
+            pos = child.end;
+        }
+        push(schema.subarray(pos, node.end));
+    };
+    write(parseNode(schema, 0));
+
+    const out = new Uint8Array(size);
+    let pos = 0;
+    for (const part of parts) {
+        out.set(part, pos);
+        pos += part.length;
+    }
+    return out;
+}
@@ -1,8 +1,22 @@
-import { OutOfDataError } from "./ReadContext";
-import { dataType, readRollingUintNoAlloc, WriteContext } from "./utils";
+import {
+    canonicalKey,
+    checkWrittenSize,
+    comparingRecursive,
+    dataType,
+    decodeHookKey,
+    getRollingIntSize,
+    getRollingUintSize,
+    readRollingIntNoAlloc,
+    readRollingUintNoAlloc,
+    typedArrayKindIds,
+    typedArrayKinds,
+    writeRollingIntNoAlloc,
+    writeRollingUintNoAlloc,
+    WriteContext,
+} from "./utils";
 import FlatPromiseStream from "./FlatPromiseStream";
 import type { output } from "./deserialize";
-import type { ReadContext } from "./ReadContext";
+import { DataError, type ReadContext } from "./ReadContext";
 
 function base<T>(
     name: string,
@@ -18,7 +32,7 @@ function base<T>(
         hijackReadContext: (
             id: number,
             cb: (ctx: ReadContext) => Promise<void>,
-            onDisconnect: () => void,
+            onDisconnect: (err: Error) => void,
         ) => (slurp: boolean) => void,
         scratchPad: { [key: symbol]: any },
     ) => Promise<[T]>,
@@ -26,14 +40,28 @@ function base<T>(
     schema: Uint8Array<ArrayBuffer>,
     extraInfo?: any,
 ) {
-    return {
+    const res = {
         name,
         validateAndMakeWriter,
-        readFromContext,
+        readFromContext: (
+            ctx: ReadContext,
+            hijackReadContext: Parameters<typeof readFromContext>[1],
+            scratchPad: { [key: symbol]: any },
+        ): Promise<[T]> => {
+            // Only wait on the read here when there is a hook, so that reads without one don't
+            // pay for an extra promise.
+            const hook = scratchPad[decodeHookKey];
+            const value = readFromContext(ctx, hijackReadContext, scratchPad);
+            if (!hook) return value;
+            return value.then(
+                async ([v]): Promise<[T]> => [await hook(res, v)],
+            );
+        },
         isCompatibleWith,
         schema,
         _extraInfo: extraInfo,
     } as const;
+    return res;
 }
 
 /**
@@ -44,13 +72,25 @@ function base<T>(
  */
 export type Schema<T> = ReturnType<typeof base<T>>;
 
-function getRollingUintSize(data: number) {
-    if (data < 0) throw new Error("Data must be a non-negative integer");
-
-    if (data < 0xfd) return 1;
-    if (data <= 0xffff) return 3;
-    if (data <= 0xffffffff) return 5;
-    return 9;
+// Values nested in each other count towards the same depth limit as reflected schemas, so a
+// small payload for a recursive schema can't run the reader out of stack.
+function nested<T>(
+    readFromContext: Parameters<typeof base<T>>[2],
+): Parameters<typeof base<T>>[2] {
+    return async (ctx, hijackReadContext, scratchPad) => {
+        if (ctx.depth >= ctx.maxDepth) {
+            throw new DataError(
+                `Value is nested more than ${ctx.maxDepth} levels deep`,
+                ctx.position,
+            );
+        }
+        ctx.depth++;
+        try {
+            return await readFromContext(ctx, hijackReadContext, scratchPad);
+        } finally {
+            ctx.depth--;
+        }
+    };
 }
 
 function getEncodedLenNoAlloc(t: string) {
@@ -97,58 +137,403 @@ export function pipe<T>(from: Schema<T>, into: (data: T) => T): Schema<T> {
         readFromContext: from.readFromContext,
         isCompatibleWith: from.isCompatibleWith,
         schema: from.schema,
-        _extraInfo: undefined,
+        _extraInfo: from,
     } as const;
 }
 
+/**
+ * Creates a schema that is only built the first time it is used, and then shared after that.
+ * Useful for defining large schemas at the top level of a module without paying to build them
+ * on import if they are never used.
+ *
+ * @template T - The type handled by the schema
+ * @param factory - Function that builds the schema. Only called once
+ * @returns A schema that behaves the same as the one returned by the factory
+ *
+ * @example
+ * ```typescript
+ * export const userSchema = lazy(() =>
+ *   object({ name: string(), age: uint() }),
+ * );
+ * ```
+ */
+export function lazy<T>(factory: () => Schema<T>): Schema<T> {
+    let built: Schema<T> | undefined;
+    const get = () => {
+        if (!built) built = factory();
+        return built;
+    };
+    return {
+        get name() {
+            return get().name;
+        },
+        validateAndMakeWriter: (data, scratchPad) =>
+            get().validateAndMakeWriter(data, scratchPad),
+        readFromContext: (ctx, hijackReadContext, scratchPad) =>
+            get().readFromContext(ctx, hijackReadContext, scratchPad),
+        isCompatibleWith: (other) => get().isCompatibleWith(other),
+        get schema() {
+            return get().schema;
+        },
+        get _extraInfo() {
+            return get()._extraInfo;
+        },
+    };
+}
+
+/**
+ * The definition a recursive() schema refers back to. This is filled in once the schema has been
+ * built, which is after anything referring to it has been created.
+ */
+export type RecursiveDefinition = { inner: Schema<any> | undefined };
+
+// The definitions recursive() is building right now, innermost last. A reference is written
+// as how many definitions out from where it is used its own definition is.
+const building: RecursiveDefinition[] = [];
+
+/**
+ * Creates a reference to a recursive definition. Used by recursive() and by reflection, so
+ * normally there is no need to call this directly.
+ *
+ * @param definition - The definition to refer to
+ * @param depth - How many definitions out the reference is. Worked out while building if not given
+ * @returns Schema that behaves the same as the definition
+ */
+export function recursiveRef(
+    definition: RecursiveDefinition,
+    depth?: number,
+): Schema<any> {
+    const get = () => {
+        if (!definition.inner) {
+            throw new Error("Recursive schema was used before it was built");
+        }
+        return definition.inner;
+    };
+    return {
+        name: "recursiveRef",
+        validateAndMakeWriter: (data, scratchPad) =>
+            get().validateAndMakeWriter(data, scratchPad),
+        readFromContext: nested((ctx, hijackReadContext, scratchPad) =>
+            get().readFromContext(ctx, hijackReadContext, scratchPad),
+        ),
+        // Comparing the definition here would never finish. The recursive() it belongs to
+        // already compares it once, so a reference only has to point at the recursive() that
+        // was compared with the other reference's. That means it is the same number of levels
+        // out.
+        isCompatibleWith: (other) =>
+            other.name === "recursiveRef" &&
+            (other._extraInfo === definition ||
+                comparingRecursive.some(
+                    ([ours, theirs]) =>
+                        ours === definition.inner &&
+                        theirs === other._extraInfo.inner,
+                )),
+        get schema() {
+            let d = depth;
+            if (d === undefined) {
+                const idx = building.lastIndexOf(definition);
+                if (idx === -1) {
+                    throw new Error(
+                        "A recursive schema can only refer to itself while it is being built",
+                    );
+                }
+                d = building.length - 1 - idx;
+            }
+            const bytes = new Uint8Array(1 + getRollingUintSize(d));
+            bytes[0] = dataType.recursiveRef;
+            writeRollingUintNoAlloc(d, bytes, 1);
+            return bytes;
+        },
+        _extraInfo: definition,
+    };
+}
+
+/**
+ * Finishes a recursive definition once the schema it stands for is built. Used by recursive()
+ * and by reflection, so normally there is no need to call this directly.
+ *
+ * @template T - The type handled by the schema
+ * @param definition - The definition references were made to
+ * @param inner - The schema the definition stands for
+ * @returns Schema for the recursive type
+ */
+export function finishRecursive<T>(
+    definition: RecursiveDefinition,
+    inner: Schema<T>,
+) {
+    definition.inner = inner;
+    return base<T>(
+        "recursive",
+        (data, scratchPad) => inner.validateAndMakeWriter(data, scratchPad),
+        async (ctx, hijackReadContext, scratchPad) =>
+            inner.readFromContext(ctx, hijackReadContext, scratchPad),
+        (other) => {
+            if (other.name !== "recursive") return false;
+            comparingRecursive.push([inner, other._extraInfo]);
+            try {
+                return inner.isCompatibleWith(other._extraInfo);
+            } finally {
+                comparingRecursive.pop();
+            }
+        },
+        new Uint8Array([dataType.recursive, ...inner.schema]),
+        inner,
+    );
+}
+
+/**
+ * Creates a schema that can refer to itself, for tree and linked list shaped data. The builder is
+ * given a reference to the schema being built, which can be used anywhere inside of it. The
+ * reference only takes a few bytes on the wire, and is resolved when reading.
+ *
+ * The reference can only be used while building. Data that refers back to itself can't be
+ * written, since it would never end.
+ *
+ * @template T - The type handled by the schema
+ * @param build - Function that builds the schema from a reference to itself
+ * @returns Schema for the recursive type
+ *
+ * @example
+ * ```typescript
+ * type Node = { value: any; children: Node[] };
+ * const node = recursive<Node>((self) =>
+ *   object({ value: any(), children: array(self) }),
+ * );
+ * ```
+ */
+export function recursive<T>(
+    build: (self: Schema<T>) => Schema<T>,
+): Schema<T> {
+    const definition: RecursiveDefinition = { inner: undefined };
+    building.push(definition);
+    let inner: Schema<T>;
+    try {
+        inner = build(recursiveRef(definition));
+    } finally {
+        building.pop();
+    }
+    return finishRecursive(definition, inner);
+}
+
+/**
+ * Builds a schema as if it were inside of the given recursive definitions, so that references
+ * to them inside it are written relative to where it is. Used by reflection, so normally there
+ * is no need to call this directly.
+ *
+ * @template T - What the builder returns
+ * @param definitions - The definitions the schema is inside of, innermost last
+ * @param build - Function that builds the schema
+ * @returns What the builder returns
+ */
+export function buildInside<T>(
+    definitions: RecursiveDefinition[],
+    build: () => T,
+): T {
+    building.push(...definitions);
+    try {
+        return build();
+    } finally {
+        building.length -= definitions.length;
+    }
+}
+
+/**
+ * Metadata that can be attached to a schema with annotate(). This is never sent over the
+ * wire, it is only there for documentation and tooling.
+ */
+export type SchemaMetadata = {
+    description?: string;
+    tags?: { [key: string]: string };
+};
+
+const metadataMap = new WeakMap<Schema<any>, SchemaMetadata>();
+
+/**
+ * Attaches metadata such as a description to a schema. The returned schema behaves exactly the
+ * same as the original and has the same binary representation, so annotating a schema does not
+ * change its hash or the data on the wire.
+ *
+ * @template T - The type handled by the schema
+ * @param schema - The schema to annotate
+ * @param metadata - The description and tags to attach
+ * @returns A copy of the schema with the metadata attached
+ *
+ * @example
+ * ```typescript
+ * const userSchema = object({
+ *   name: annotate(string(), { description: "The display name of the user" }),
+ * });
+ * ```
+ */
+export function annotate<T>(
+    schema: Schema<T>,
+    metadata: SchemaMetadata,
+): Schema<T> {
+    const annotated = { ...schema };
+    metadataMap.set(annotated, metadata);
+    return annotated;
+}
+
+/**
+ * Gets the metadata attached to a schema with annotate().
+ *
+ * @param schema - The schema to get the metadata for
+ * @returns The metadata, or undefined if the schema is not annotated
+ */
+export function getMetadata(schema: Schema<any>): SchemaMetadata | undefined {
+    return metadataMap.get(schema);
+}
+
+/**
+ * Makes a schema always write the same bytes for the same data, which is needed when the output
+ * is signed, hashed, or deduplicated. Inside of it:
+ * - Object fields and record keys are sorted, as they always are.
+ * - Map entries and Set elements are sorted by their encoded bytes, rather than being written
+ *   in insertion order.
+ * - NaN in float() and float32() is always written with the same bits, rather than whichever
+ *   NaN the engine has.
+ * - Unions pick the first option that matches, as they always do.
+ *
+ * compressionTable() can't be used inside a Map or Set here, since sorting the entries would
+ * move references to earlier entries out of order. Using it anywhere else is fine.
+ *
+ * The schema has the same binary representation as the original, so reading is unchanged.
+ *
+ * @template T - The type handled by the schema
+ * @param schema - The schema to make canonical
+ * @returns A copy of the schema that writes in canonical form
+ *
+ * @example
+ * ```typescript
+ * const bytes = await serializeToUint8Array(canonical(tagsSchema), new Set(["b", "a"]));
+ * // The same bytes as for new Set(["a", "b"])
+ * ```
+ */
+export function canonical<T>(schema: Schema<T>): Schema<T> {
+    return {
+        ...schema,
+        validateAndMakeWriter: (data, scratchPad) => {
+            const previous = scratchPad[canonicalKey];
+            scratchPad[canonicalKey] = true;
+            try {
+                return schema.validateAndMakeWriter(data, scratchPad);
+            } finally {
+                scratchPad[canonicalKey] = previous;
+            }
+        },
+    };
+}
+
+function compareBytes(a: Uint8Array, b: Uint8Array) {
+    const len = Math.min(a.length, b.length);
+    for (let i = 0; i < len; i++) {
+        if (a[i] !== b[i]) return a[i] - b[i];
+    }
+    return a.length - b.length;
+}
+
+// Set while validating the entries of a map or set that canonical() will sort. Compression
+// tables hand out indexes in validation order, so sorting would put a reference to an entry
+// before the entry itself.
+const sortingEntriesKey = Symbol("sortingEntries");
+
+function validateSortedEntries<T>(
+    scratchPad: { [key: symbol]: any },
+    validate: () => T,
+): T {
+    if (scratchPad[canonicalKey] !== true) return validate();
+    const previous = scratchPad[sortingEntriesKey];
+    scratchPad[sortingEntriesKey] = true;
+    try {
+        return validate();
+    } finally {
+        scratchPad[sortingEntriesKey] = previous;
+    }
+}
+
+// Writes each entry into its own buffer first so that they can be put in byte order.
+function writeSortedByBytes(
+    ctx: WriteContext,
+    entries: [number, (ctx: WriteContext) => void][],
+) {
+    const encoded = entries.map(([size, writer]) => {
+        const buf = new Uint8Array(size);
+        writer({ ...ctx, buf, pos: 0 });
+        return buf;
+    });
+    encoded.sort(compareBytes);
+    for (const buf of encoded) {
+        ctx.buf.set(buf, ctx.pos);
+        ctx.pos += buf.length;
+    }
+}
+
 /**
  * Error thrown when data validation fails during schema processing.
  * Contains a descriptive message about what validation rule was violated.
+ *
+ * When the failure happens inside a nested schema, path holds the segments leading to the
+ * value that failed (such as `["user", "tags", "[2]"]`) and the message is prefixed with it,
+ * for example `Validation error at user.tags[2]: Data must be a string`. The message without
+ * the path is kept in reason.
  */
 export class ValidationError extends Error {
+    readonly reason: string;
+    readonly path: string[] = [];
+
     constructor(message: string) {
         super(message);
         this.name = "ValidationError";
+        this.reason = message;
     }
-}
-
-function writeRollingUintNoAlloc(data: number, u8a: Uint8Array, pos: number) {
-    if (data < 0) throw new Error("Data must be a non-negative integer");
 
-    if (data < 0xfd) {
-        u8a[pos] = data;
-        return pos + 1;
-    }
-    if (data <= 0xffff) {
-        u8a[pos] = 0xfd;
-        u8a[pos + 1] = data & 0xff;
-        u8a[pos + 2] = (data >> 8) & 0xff;
-        return pos + 3;
+    /**
+     * Adds a segment to the start of the path and rebuilds the message. Used by composite schemas
+     * as the error bubbles up through them.
+     *
+     * @param segment - A property name, or an index or key wrapped in square brackets
+     * @returns This error
+     */
+    prependPath(segment: string) {
+        this.path.unshift(segment);
+        let rendered = "";
+        for (const part of this.path) {
+            rendered +=
+                rendered === "" || part.startsWith("[") ? part : `.${part}`;
+        }
+        this.message = `Validation error at ${rendered}: ${this.reason}`;
+        return this;
     }
-    if (data <= 0xffffffff) {
-        u8a[pos] = 0xfe;
-        u8a[pos + 1] = data & 0xff;
-        u8a[pos + 2] = (data >> 8) & 0xff;
-        u8a[pos + 3] = (data >> 16) & 0xff;
-        u8a[pos + 4] = (data >> 24) & 0xff;
-        return pos + 5;
+}
+
+function validateAt(
+    segment: string,
+    schema: Schema<any>,
+    data: unknown,
+    scratchPad: { [key: symbol]: any },
+) {
+    try {
+        return schema.validateAndMakeWriter(data, scratchPad);
+    } catch (err) {
+        if (err instanceof ValidationError) err.prependPath(segment);
+        throw err;
     }
-    u8a[pos] = 0xff;
-    u8a[pos + 1] = data & 0xff;
-    u8a[pos + 2] = (data >> 8) & 0xff;
-    u8a[pos + 3] = (data >> 16) & 0xff;
-    u8a[pos + 4] = (data >> 24) & 0xff;
-    u8a[pos + 5] = (data >> 32) & 0xff;
-    u8a[pos + 6] = (data >> 40) & 0xff;
-    u8a[pos + 7] = (data >> 48) & 0xff;
-    u8a[pos + 8] = (data >> 56) & 0xff;
-    return pos + 9;
+}
+
+function describeKey(key: unknown) {
+    if (typeof key === "string") return `[${JSON.stringify(key)}]`;
+    if (typeof key === "bigint") return `[${key}n]`;
+    return `[${String(key)}]`;
 }
 
 /**
  * Creates a schema for arrays containing elements of a specific type.
  * Validates that data is an array and that all elements conform to the element schema.
  *
+ * Each element is validated in the same pass that works out its size and writer, so checking
+ * every element costs no more than writing it. There is no sampled mode, since every element has
+ * to be visited to be written anyway.
+ *
  * @template T - The type of elements in the array
  * @param elements - Schema defining the structure of array elements
  * @param message - Optional custom validation error message
@@ -171,9 +556,11 @@ export function array<T>(elements: Schema<T>, message?: string) {
             if (!Array.isArray(data)) throw new ValidationError(message);
             let size = getRollingUintSize(data.length);
             const writers: ((ctx: WriteContext) => void)[] = [];
-            for (const item of data) {
-                const [s, writer] = elements.validateAndMakeWriter(
-                    item,
+            for (let i = 0; i < data.length; i++) {
+                const [s, writer] = validateAt(
+                    `[${i}]`,
+                    elements,
+                    data[i],
                     scratchPad,
                 );
                 size += s;
@@ -193,7 +580,7 @@ export function array<T>(elements: Schema<T>, message?: string) {
                 },
             ];
         },
-        async (ctx, hijackReadContext, scratchPad) => {
+        nested(async (ctx, hijackReadContext, scratchPad) => {
             const len = await readRollingUintNoAlloc(ctx);
             const res: T[] = [];
             for (let i = 0; i < len; i++) {
@@ -205,7 +592,7 @@ export function array<T>(elements: Schema<T>, message?: string) {
                 res.push(item[0]);
             }
             return [res];
-        },
+        }),
         (other) => {
             if (other.name !== "array") return false;
             return elements.isCompatibleWith(other._extraInfo);
@@ -215,85 +602,179 @@ export function array<T>(elements: Schema<T>, message?: string) {
     );
 }
 
-/**
- * Type definition for object schemas - a mapping from string keys to schema definitions.
- * Used as input to the object() schema function to define object structure.
- */
-export type ObjectSchemas = {
-    [key: string]: Schema<any>;
-};
+// Set elements are compared by their encoded bytes, so this turns them into a string that can
+// be kept in a JS Set.
+function bytesKey(bytes: Uint8Array) {
+    let key = "";
+    for (let i = 0; i < bytes.length; i++) {
+        key += String.fromCharCode(bytes[i]);
+    }
+    return key;
+}
 
-const te = new TextEncoder();
+function rejectStreamInSet(): never {
+    throw new ValidationError("Set elements can't contain streamed types");
+}
+
+// Writes a set element into its own buffer, so that it can be compared with the others.
+function encodeSetElement([size, writer]: [
+    number,
+    (ctx: WriteContext) => void,
+]) {
+    const buf = new Uint8Array(size);
+    writer({
+        buf,
+        pos: 0,
+        createWriteStream: rejectStreamInSet,
+        runStream: rejectStreamInSet,
+    });
+    return buf;
+}
 
 /**
- * Creates a schema for objects with predefined properties and their schemas.
- * Validates that data is an object and that all properties conform to their defined schemas.
- * Properties are processed in alphabetical order for consistent serialization.
+ * Creates a schema for Sets containing elements of a specific type.
+ * Elements are written like an array, and must be unique. Elements are compared by how they
+ * are encoded, so two objects with the same contents are rejected even though a Set would keep
+ * both, as are two values the element schema writes the same way (such as objects that only
+ * differ in properties the schema doesn't have). Reading a Set with duplicate elements fails
+ * the same way, so a corrupted payload can't add repeats.
  *
- * @template T - Object schema definition mapping property names to schemas
- * @param schemas - Object defining the schema for each property
+ * Each element is encoded on its own to be compared, so elements can't contain promises,
+ * iterators, or readable streams.
+ *
+ * @template T - The type of elements in the Set
+ * @param elements - Schema defining the structure of the elements
  * @param message - Optional custom validation error message
- * @returns Schema for objects with the specified structure
+ * @returns Schema for Set<T> values
  *
  * @example
  * ```typescript
- * const userSchema = object({
- *   name: string(),
- *   age: uint(),
- *   email: optional(string())
- * });
+ * const tags = set(string());
  * ```
  */
-export function object<T extends ObjectSchemas>(schemas: T, message?: string) {
-    if (!message) message = "Data must be an object";
+export function set<T>(elements: Schema<T>, message?: string) {
+    if (!message) message = "Data must be a Set";
 
-    const keys = Object.keys(schemas).sort((a, b) => a.localeCompare(b));
-    let schemaLen = 1 + getRollingUintSize(keys.length); // 1 byte for dataType, plus key count
-    for (const key of keys) {
-        schemaLen += getRollingUintSize(key.length) + key.length;
-    }
+    const schema = new Uint8Array([dataType.set, ...elements.schema]);
 
-    const schemaParts: Uint8Array[] = keys.map((key) => {
-        schemaLen += schemas[key].schema.length;
-        return schemas[key].schema;
-    });
+    return base<Set<T>>(
+        "set",
+        (data, scratchPad) => {
+            if (!(data instanceof Set)) throw new ValidationError(message);
+            const items = Array.from(data);
+            let size = getRollingUintSize(items.length);
+            const encoded: Uint8Array[] = [];
+            const seen = new Set<string>();
+            validateSortedEntries(scratchPad, () => {
+                for (let i = 0; i < items.length; i++) {
+                    const bytes = encodeSetElement(
+                        validateAt(`[${i}]`, elements, items[i], scratchPad),
+                    );
+                    const key = bytesKey(bytes);
+                    if (seen.has(key)) {
+                        throw new ValidationError(
+                            "Set contains duplicate elements",
+                        ).prependPath(`[${i}]`);
+                    }
+                    seen.add(key);
+                    size += bytes.length;
+                    encoded.push(bytes);
+                }
+            });
+            if (scratchPad[canonicalKey] === true) encoded.sort(compareBytes);
+            return [
+                size,
+                (ctx: WriteContext) => {
+                    ctx.pos = writeRollingUintNoAlloc(
+                        items.length,
+                        ctx.buf,
+                        ctx.pos,
+                    );
+                    for (const bytes of encoded) {
+                        ctx.buf.set(bytes, ctx.pos);
+                        ctx.pos += bytes.length;
+                    }
+                },
+            ];
+        },
+        async (ctx, hijackReadContext, scratchPad) => {
+            const len = await readRollingUintNoAlloc(ctx);
+            const res = new Set<T>();
+            const seen = new Set<string>();
+            for (let i = 0; i < len; i++) {
+                const offset = ctx.position;
+                const start = ctx.startRecording();
+                const item = await elements.readFromContext(
+                    ctx,
+                    hijackReadContext,
+                    scratchPad,
+                );
+                const key = bytesKey(ctx.stopRecording(start));
+                if (seen.has(key)) {
+                    throw new DataError(
+                        "Set contains duplicate elements",
+                        offset,
+                    );
+                }
+                seen.add(key);
+                res.add(item[0]);
+            }
+            return [res];
+        },
+        (other) => {
+            if (other.name !== "set") return false;
+            return elements.isCompatibleWith(other._extraInfo);
+        },
+        schema,
+        elements,
+    );
+}
 
-    const schema = new Uint8Array(schemaLen);
-    schema[0] = dataType.object;
-    let pos = writeRollingUintNoAlloc(keys.length, schema, 1);
-    for (let i = 0; i < keys.length; i++) {
-        // Write key
-        const key = keys[i];
-        const keyLen = getEncodedLenNoAlloc(key);
-        pos = writeRollingUintNoAlloc(key.length, schema, pos);
-        te.encodeInto(key, schema.subarray(pos, pos + keyLen));
-        pos += keyLen;
+/**
+ * Creates a schema for fixed length arrays where each position has its own type.
+ * Validates that data is an array of exactly the right length and that each element
+ * conforms to the schema for its position. The length is part of the schema, so it
+ * is not written with the data.
+ *
+ * @template T - The tuple of element schemas
+ * @param elements - Schemas for each position in the tuple
+ * @returns Schema for tuples of the given element types
+ *
+ * @example
+ * ```typescript
+ * const point = tuple(float(), float());
+ * const entry = tuple(uint(), string(), boolean()); // [number, string, boolean]
+ * ```
+ */
+export function tuple<T extends Schema<any>[]>(...elements: T) {
+    const message = `Data must be an array of length ${elements.length}`;
 
-        // Write schema part
-        const part = schemaParts[i];
-        schema.set(part, pos);
-        pos += part.length;
+    let schemaLen = 1 + getRollingUintSize(elements.length); // 1 byte for dataType, plus length
+    for (const sch of elements) {
+        schemaLen += sch.schema.length;
     }
 
-    type Resolved = {
-        [K in keyof T]: T[K] extends Schema<infer U> ? U : never;
-    };
+    const schema = new Uint8Array(schemaLen);
+    schema[0] = dataType.tuple;
+    let pos = writeRollingUintNoAlloc(elements.length, schema, 1);
+    for (const sch of elements) {
+        schema.set(sch.schema, pos);
+        pos += sch.schema.length;
+    }
 
-    return base<Resolved>(
-        "object",
+    return base<{ [K in keyof T]: T[K] extends Schema<infer U> ? U : never }>(
+        "tuple",
         (data, scratchPad) => {
-            if (
-                typeof data !== "object" ||
-                data === null ||
-                Array.isArray(data)
-            ) {
+            if (!Array.isArray(data) || data.length !== elements.length) {
                 throw new ValidationError(message);
             }
             let size = 0;
             const writers: ((ctx: WriteContext) => void)[] = [];
-            for (const key of keys) {
-                const [s, writer] = schemas[key].validateAndMakeWriter(
-                    (data as any)[key],
+            for (let i = 0; i < elements.length; i++) {
+                const [s, writer] = validateAt(
+                    `[${i}]`,
+                    elements[i],
+                    data[i],
                     scratchPad,
                 );
                 size += s;
@@ -309,37 +790,618 @@ export function object<T extends ObjectSchemas>(schemas: T, message?: string) {
             ];
         },
         async (ctx, hijackReadContext, scratchPad) => {
-            const res: any = {};
-            for (const key of keys) {
-                const value = await schemas[key].readFromContext(
+            const res: any[] = [];
+            for (const element of elements) {
+                const item = await element.readFromContext(
                     ctx,
                     hijackReadContext,
                     scratchPad,
                 );
-                res[key] = value[0];
+                res.push(item[0]);
             }
-            return [res as Resolved];
+            return [res as any];
         },
         (other) => {
-            if (other.name !== "object") return false;
-            const otherSchemas = other._extraInfo as ObjectSchemas;
-            for (const [key, schema] of Object.entries(otherSchemas)) {
-                const ourVersion = schemas[key];
-                if (ourVersion) {
-                    if (!ourVersion.isCompatibleWith(schema)) {
-                        // Schemas for this key are incompatible
-                        return false;
-                    }
-                } else {
-                    if (schema.name !== "optional") {
-                        // If it isn't optional, schemas are incompatible
-                        return false;
-                    }
+            if (other.name !== "tuple") return false;
+            const otherSchemas = other._extraInfo as Schema<any>[];
+            if (otherSchemas.length !== elements.length) return false;
+            for (let i = 0; i < elements.length; i++) {
+                if (!elements[i].isCompatibleWith(otherSchemas[i])) {
+                    return false;
                 }
             }
             return true;
         },
-        schema,
+        schema,
+        elements,
+    );
+}
+
+/**
+ * Type definition for object schemas - a mapping from string keys to schema definitions.
+ * Used as input to the object() schema function to define object structure.
+ */
+export type ObjectSchemas = {
+    [key: string]: Schema<any>;
+};
+
+const te = new TextEncoder();
+
+// Keys that decide the order of things on the wire are sorted by UTF-16 code unit rather than
+// with localeCompare(), which depends on the host's locale and ICU version and treats some
+// distinct strings as equal. object() keeps localeCompare() so its schemas stay the same.
+function compareCodeUnits(a: string, b: string) {
+    return a < b ? -1 : a > b ? 1 : 0;
+}
+
+function makeObjectSchemaBytes(
+    type: number,
+    keys: string[],
+    schemas: ObjectSchemas,
+) {
+    let schemaLen = 1 + getRollingUintSize(keys.length); // 1 byte for dataType, plus key count
+    const keyLens = keys.map((key) => {
+        const keyLen = getEncodedLenNoAlloc(key);
+        schemaLen += getRollingUintSize(keyLen) + keyLen;
+        schemaLen += schemas[key].schema.length;
+        return keyLen;
+    });
+
+    const schema = new Uint8Array(schemaLen);
+    schema[0] = type;
+    let pos = writeRollingUintNoAlloc(keys.length, schema, 1);
+    for (let i = 0; i < keys.length; i++) {
+        // Write key
+        const key = keys[i];
+        const keyLen = keyLens[i];
+        pos = writeRollingUintNoAlloc(keyLen, schema, pos);
+        te.encodeInto(key, schema.subarray(pos, pos + keyLen));
+        pos += keyLen;
+
+        // Write schema part
+        const part = schemas[key].schema;
+        schema.set(part, pos);
+        pos += part.length;
+    }
+    return schema;
+}
+
+function objectFieldsCompatible(
+    schemas: ObjectSchemas,
+    otherSchemas: ObjectSchemas,
+) {
+    for (const [key, schema] of Object.entries(otherSchemas)) {
+        const ourVersion = schemas[key];
+        if (ourVersion) {
+            if (!ourVersion.isCompatibleWith(schema)) {
+                // Schemas for this key are incompatible
+                return false;
+            }
+        } else {
+            if (schema.name !== "optional") {
+                // If it isn't optional, schemas are incompatible
+                return false;
+            }
+        }
+    }
+    return true;
+}
+
+/**
+ * Creates a schema for objects with predefined properties and their schemas.
+ * Validates that data is an object and that all properties conform to their defined schemas.
+ * Properties are processed in alphabetical order for consistent serialization. Use
+ * orderedObject() to keep them in the order they are given.
+ *
+ * @template T - Object schema definition mapping property names to schemas
+ * @param schemas - Object defining the schema for each property
+ * @param message - Optional custom validation error message
+ * @returns Schema for objects with the specified structure
+ *
+ * @example
+ * ```typescript
+ * const userSchema = object({
+ *   name: string(),
+ *   age: uint(),
+ *   email: optional(string())
+ * });
+ * ```
+ */
+export function object<T extends ObjectSchemas>(schemas: T, message?: string) {
+    const keys = Object.keys(schemas).sort((a, b) => a.localeCompare(b));
+    return makeObject("object", dataType.object, keys, schemas, message);
+}
+
+/**
+ * Creates a schema for objects from a list of field names and schemas, such as one built up
+ * at runtime. Like object(), the fields are sorted alphabetically once when the schema is
+ * created and written in that order. The list passed in is not changed. Use orderedObject()
+ * with Object.fromEntries() to keep the order of the list instead.
+ *
+ * @param fields - Iterable of [name, schema] pairs
+ * @param message - Optional custom validation error message
+ * @returns Schema for objects with the specified structure
+ * @throws {Error} If the same field name is given more than once
+ *
+ * @example
+ * ```typescript
+ * const columns = new Map([["id", uint()], ["name", string()]]);
+ * const rowSchema = objectFromEntries(columns);
+ * ```
+ */
+export function objectFromEntries(
+    fields: Iterable<[string, Schema<any>]>,
+    message?: string,
+) {
+    const schemas: ObjectSchemas = {};
+    for (const [key, schema] of fields) {
+        if (Object.prototype.hasOwnProperty.call(schemas, key)) {
+            throw new Error(`Duplicate field name: ${key}`);
+        }
+        schemas[key] = schema;
+    }
+    return object(schemas, message);
+}
+
+/**
+ * Creates a schema for objects with predefined properties, like object(), but keeps the
+ * properties in the order they are given instead of sorting them. This is useful for matching
+ * the layout of another implementation that writes fields in declaration order.
+ *
+ * The order is part of the schema, so two ordered objects with the same fields in a different
+ * order have different hashes.
+ *
+ * @template T - Object schema definition mapping property names to schemas
+ * @param schemas - Object defining the schema for each property, in the order to write them
+ * @param message - Optional custom validation error message
+ * @returns Schema for objects with the specified structure
+ *
+ * @example
+ * ```typescript
+ * const userSchema = orderedObject({
+ *   name: string(),
+ *   age: uint(),
+ * }); // name is written before age
+ * ```
+ */
+export function orderedObject<T extends ObjectSchemas>(
+    schemas: T,
+    message?: string,
+) {
+    const keys = Object.keys(schemas);
+    return makeObject(
+        "orderedObject",
+        dataType.orderedObject,
+        keys,
+        schemas,
+        message,
+    );
+}
+
+function makeObject<T extends ObjectSchemas>(
+    name: string,
+    type: number,
+    keys: string[],
+    schemas: T,
+    message?: string,
+) {
+    if (!message) message = "Data must be an object";
+
+    const schema = makeObjectSchemaBytes(type, keys, schemas);
+
+    type Resolved = {
+        [K in keyof T]: T[K] extends Schema<infer U> ? U : never;
+    };
+
+    return base<Resolved>(
+        name,
+        (data, scratchPad) => {
+            if (
+                typeof data !== "object" ||
+                data === null ||
+                Array.isArray(data)
+            ) {
+                throw new ValidationError(message);
+            }
+            let size = 0;
+            const writers: ((ctx: WriteContext) => void)[] = [];
+            for (const key of keys) {
+                const [s, writer] = validateAt(
+                    key,
+                    schemas[key],
+                    (data as any)[key],
+                    scratchPad,
+                );
+                size += s;
+                writers.push(writer);
+            }
+            return [
+                size,
+                (ctx: WriteContext) => {
+                    for (const writer of writers) {
+                        writer(ctx);
+                    }
+                },
+            ];
+        },
+        nested(async (ctx, hijackReadContext, scratchPad) => {
+            const res: any = {};
+            for (const key of keys) {
+                const value = await schemas[key].readFromContext(
+                    ctx,
+                    hijackReadContext,
+                    scratchPad,
+                );
+                res[key] = value[0];
+            }
+            return [res as Resolved];
+        }),
+        (other) => {
+            if (other.name !== name) return false;
+            return objectFieldsCompatible(schemas, other._extraInfo);
+        },
+        schema,
+        schemas,
+    );
+}
+
+/**
+ * Creates a schema for objects where each field describes itself on the wire.
+ * Every field is written alongside its name and its own schema, so a reader using an older
+ * version of the schema can skip fields that were added by the writer. Only the type byte
+ * is part of the schema header, meaning adding or removing fields does not change the hash.
+ *
+ * @template T - Object schema definition mapping property names to schemas
+ * @param schemas - Object defining the schema for each property
+ * @param message - Optional custom validation error message
+ * @param keepUnknown - If true, fields not in the schema are decoded and kept instead of skipped
+ * @returns Schema for objects with the specified structure
+ *
+ * @example
+ * ```typescript
+ * // The writer has since added a field that older readers don't know about.
+ * const writerSchema = selfDescribingObject({ name: string(), age: uint() });
+ * const readerSchema = selfDescribingObject({ name: string() }); // age is skipped
+ * ```
+ */
+export function selfDescribingObject<T extends ObjectSchemas>(
+    schemas: T,
+    message?: string,
+    keepUnknown?: boolean,
+) {
+    if (!message) message = "Data must be an object";
+
+    const keys = Object.keys(schemas).sort(compareCodeUnits);
+    const encodedKeys = keys.map((key) => te.encode(key));
+
+    // Each field's schema is written next to its value. A field can refer to a recursive() this
+    // is inside of, which only has a position while it is being built, so take the bytes now and
+    // remember which definitions the references are relative to for reading them back.
+    const fieldSchemas = keys.map((key) => schemas[key].schema);
+    const enclosing = building.slice();
+
+    type Resolved = {
+        [K in keyof T]: T[K] extends Schema<infer U> ? U : never;
+    };
+
+    return base<Resolved>(
+        "selfDescribingObject",
+        (data, scratchPad) => {
+            if (
+                typeof data !== "object" ||
+                data === null ||
+                Array.isArray(data)
+            ) {
+                throw new ValidationError(message);
+            }
+            let size = getRollingUintSize(keys.length);
+            const writers: ((ctx: WriteContext) => void)[] = [];
+            for (let i = 0; i < keys.length; i++) {
+                const fieldSchema = schemas[keys[i]];
+                const [s, writer] = validateAt(
+                    keys[i],
+                    fieldSchema,
+                    (data as any)[keys[i]],
+                    scratchPad,
+                );
+                size +=
+                    getRollingUintSize(encodedKeys[i].length) +
+                    encodedKeys[i].length +
+                    fieldSchemas[i].length +
+                    s;
+                writers.push(writer);
+            }
+            return [
+                size,
+                (ctx: WriteContext) => {
+                    ctx.pos = writeRollingUintNoAlloc(
+                        keys.length,
+                        ctx.buf,
+                        ctx.pos,
+                    );
+                    for (let i = 0; i < keys.length; i++) {
+                        // Write the key
+                        const key = encodedKeys[i];
+                        ctx.pos = writeRollingUintNoAlloc(
+                            key.length,
+                            ctx.buf,
+                            ctx.pos,
+                        );
+                        ctx.buf.set(key, ctx.pos);
+                        ctx.pos += key.length;
+
+                        // Write the schema for the field followed by the value
+                        const fieldSchema = fieldSchemas[i];
+                        ctx.buf.set(fieldSchema, ctx.pos);
+                        ctx.pos += fieldSchema.length;
+                        writers[i](ctx);
+                    }
+                },
+            ];
+        },
+        nested(async (ctx, hijackReadContext, scratchPad) => {
+            const { reflectByteReprToSchema } = await import("./reflection");
+            const start = ctx.position;
+            const numFields = await readRollingUintNoAlloc(ctx);
+            const res: any = {};
+            for (let i = 0; i < numFields; i++) {
+                const offset = ctx.position;
+                const keyLen = await readRollingUintNoAlloc(ctx);
+                const key = td.decode(await ctx.readBytes(keyLen));
+                if (key === "prototype" || key === "__proto__") {
+                    throw new DataError(
+                        "Object contains invalid field name",
+                        offset,
+                    );
+                }
+
+                // The value has to be read either way to get past it.
+                const wireSchema = await reflectByteReprToSchema(
+                    ctx,
+                    enclosing,
+                );
+                const value = await wireSchema.readFromContext(
+                    ctx,
+                    hijackReadContext,
+                    scratchPad,
+                );
+
+                if (!Object.prototype.hasOwnProperty.call(schemas, key)) {
+                    if (keepUnknown) {
+                        res[key] = value[0];
+                    }
+                    continue;
+                }
+                if (!wireSchema.isCompatibleWith(schemas[key])) {
+                    throw new DataError(
+                        `Incompatible schema received for field ${key}`,
+                        offset,
+                    );
+                }
+                res[key] = value[0];
+            }
+            for (const key of keys) {
+                if (
+                    !Object.prototype.hasOwnProperty.call(res, key) &&
+                    schemas[key].name !== "optional"
+                ) {
+                    throw new DataError(`Missing required field ${key}`, start);
+                }
+            }
+            return [res as Resolved];
+        }),
+        rejectIfNotSameName("selfDescribingObject"),
+        new Uint8Array([dataType.selfDescribingObject]),
+        schemas,
+    );
+}
+
+/**
+ * Creates a schema for objects with many optional properties that are usually missing.
+ * This behaves the same as object(), but instead of a byte per optional property saying if
+ * it is there, a single bitmap with a bit per optional property is written at the start.
+ *
+ * @template T - Object schema definition mapping property names to schemas
+ * @param schemas - Object defining the schema for each property
+ * @param message - Optional custom validation error message
+ * @returns Schema for objects with the specified structure
+ *
+ * @example
+ * ```typescript
+ * const settings = sparseObject({
+ *   theme: optional(string()),
+ *   fontSize: optional(uint()),
+ *   language: optional(string()),
+ * });
+ * ```
+ */
+export function sparseObject<T extends ObjectSchemas>(
+    schemas: T,
+    message?: string,
+) {
+    if (!message) message = "Data must be an object";
+
+    const keys = Object.keys(schemas).sort(compareCodeUnits);
+    const schema = makeObjectSchemaBytes(dataType.sparseObject, keys, schemas);
+    const optionalCount = keys.filter(
+        (key) => schemas[key].name === "optional",
+    ).length;
+    const bitmapLen = Math.ceil(optionalCount / 8);
+
+    type Resolved = {
+        [K in keyof T]: T[K] extends Schema<infer U> ? U : never;
+    };
+
+    return base<Resolved>(
+        "sparseObject",
+        (data, scratchPad) => {
+            if (
+                typeof data !== "object" ||
+                data === null ||
+                Array.isArray(data)
+            ) {
+                throw new ValidationError(message);
+            }
+            let size = bitmapLen;
+            const bitmap = new Uint8Array(bitmapLen);
+            let bit = 0;
+            const writers: ((ctx: WriteContext) => void)[] = [];
+            for (const key of keys) {
+                let fieldSchema = schemas[key];
+                const value = (data as any)[key];
+                if (fieldSchema.name === "optional") {
+                    const i = bit++;
+                    if (value === undefined) continue;
+                    bitmap[i >> 3] |= 1 << (i & 7);
+                    fieldSchema = fieldSchema._extraInfo;
+                }
+                const [s, writer] = validateAt(
+                    key,
+                    fieldSchema,
+                    value,
+                    scratchPad,
+                );
+                size += s;
+                writers.push(writer);
+            }
+            return [
+                size,
+                (ctx: WriteContext) => {
+                    ctx.buf.set(bitmap, ctx.pos);
+                    ctx.pos += bitmapLen;
+                    for (const writer of writers) {
+                        writer(ctx);
+                    }
+                },
+            ];
+        },
+        async (ctx, hijackReadContext, scratchPad) => {
+            const bitmap = await ctx.readBytes(bitmapLen);
+            let bit = 0;
+            const res: any = {};
+            for (const key of keys) {
+                let fieldSchema = schemas[key];
+                if (fieldSchema.name === "optional") {
+                    const i = bit++;
+                    if (!(bitmap[i >> 3] & (1 << (i & 7)))) {
+                        res[key] = undefined;
+                        continue;
+                    }
+                    fieldSchema = fieldSchema._extraInfo;
+                }
+                const value = await fieldSchema.readFromContext(
+                    ctx,
+                    hijackReadContext,
+                    scratchPad,
+                );
+                res[key] = value[0];
+            }
+            return [res as Resolved];
+        },
+        (other) => {
+            if (other.name !== "sparseObject") return false;
+            return objectFieldsCompatible(schemas, other._extraInfo);
+        },
+        schema,
+        schemas,
+    );
+}
+
+/**
+ * Creates a schema for partial updates to an object, where any of the properties may be left
+ * out. A bitmap at the start says which properties are present, and only those are written.
+ * Unlike making every property optional, properties that were left out are not in the object
+ * that is read back at all, so it can tell "not changed" apart from "set to undefined" when
+ * used for patch APIs.
+ *
+ * A property counts as present if it is set to anything other than undefined.
+ *
+ * @template T - The object type being updated
+ * @param schema - An object schema, such as one from object() or sparseObject()
+ * @param message - Optional custom validation error message
+ * @returns Schema for partial objects of type T
+ * @throws {Error} If the schema is not an object schema
+ *
+ * @example
+ * ```typescript
+ * const userPatch = partial(userSchema);
+ * const patch = { name: "Jane" }; // Only name is written and read back
+ * ```
+ */
+export function partial<T extends { [key: string]: any }>(
+    schema: Schema<T>,
+    message?: string,
+) {
+    if (
+        schema.name !== "object" &&
+        schema.name !== "orderedObject" &&
+        schema.name !== "sparseObject" &&
+        schema.name !== "selfDescribingObject"
+    ) {
+        throw new Error("partial() can only be used with object schemas");
+    }
+    if (!message) message = "Data must be an object";
+
+    const schemas = schema._extraInfo as ObjectSchemas;
+    const keys = Object.keys(schemas).sort(compareCodeUnits);
+    const bitmapLen = Math.ceil(keys.length / 8);
+
+    return base<Partial<T>>(
+        "partial",
+        (data, scratchPad) => {
+            if (
+                typeof data !== "object" ||
+                data === null ||
+                Array.isArray(data)
+            ) {
+                throw new ValidationError(message);
+            }
+            let size = bitmapLen;
+            const bitmap = new Uint8Array(bitmapLen);
+            const writers: ((ctx: WriteContext) => void)[] = [];
+            for (let i = 0; i < keys.length; i++) {
+                const value = (data as any)[keys[i]];
+                if (value === undefined) continue;
+                bitmap[i >> 3] |= 1 << (i & 7);
+                const [s, writer] = validateAt(
+                    keys[i],
+                    schemas[keys[i]],
+                    value,
+                    scratchPad,
+                );
+                size += s;
+                writers.push(writer);
+            }
+            return [
+                size,
+                (ctx: WriteContext) => {
+                    ctx.buf.set(bitmap, ctx.pos);
+                    ctx.pos += bitmapLen;
+                    for (const writer of writers) {
+                        writer(ctx);
+                    }
+                },
+            ];
+        },
+        async (ctx, hijackReadContext, scratchPad) => {
+            const bitmap = await ctx.readBytes(bitmapLen);
+            const res: any = {};
+            for (let i = 0; i < keys.length; i++) {
+                if (!(bitmap[i >> 3] & (1 << (i & 7)))) continue;
+                const value = await schemas[keys[i]].readFromContext(
+                    ctx,
+                    hijackReadContext,
+                    scratchPad,
+                );
+                res[keys[i]] = value[0];
+            }
+            return [res as Partial<T>];
+        },
+        (other) => {
+            if (other.name !== "partial") return false;
+            return objectFieldsCompatible(schemas, other._extraInfo);
+        },
+        makeObjectSchemaBytes(dataType.partial, keys, schemas),
         schemas,
     );
 }
@@ -434,6 +1496,54 @@ export function uint8array(message?: string) {
     );
 }
 
+/**
+ * Creates a schema for byte arrays that are always the same length, such as hashes, keys, and
+ * fixed size IDs. The length is part of the schema, so unlike uint8array() it is not written
+ * with the data.
+ *
+ * @param length - The number of bytes every value must have
+ * @param message - Optional custom validation error message
+ * @returns Schema for Uint8Array values of the given length
+ *
+ * @example
+ * ```typescript
+ * const sha256 = fixedBytes(32); // always 32 bytes on the wire
+ * ```
+ */
+export function fixedBytes(length: number, message?: string) {
+    if (!Number.isInteger(length) || length < 0) {
+        throw new Error(`Invalid fixed byte length: ${length}`);
+    }
+    if (!message) message = `Data must be a Uint8Array of length ${length}`;
+
+    const schema = new Uint8Array(1 + getRollingUintSize(length));
+    schema[0] = dataType.fixedBytes;
+    writeRollingUintNoAlloc(length, schema, 1);
+
+    return base<Uint8Array>(
+        "fixedBytes",
+        (data) => {
+            if (!(data instanceof Uint8Array) || data.length !== length) {
+                throw new ValidationError(message);
+            }
+            return [
+                length,
+                (ctx: WriteContext) => {
+                    ctx.buf.set(data, ctx.pos);
+                    ctx.pos += length;
+                },
+            ];
+        },
+        async (ctx) => {
+            const bytes = await ctx.readBytes(length);
+            return [bytes];
+        },
+        (other) => other.name === "fixedBytes" && other._extraInfo === length,
+        schema,
+        length,
+    );
+}
+
 /**
  * Creates a schema for Node.js Buffer objects.
  * Validates that data is a Buffer and handles efficient binary serialization.
@@ -535,8 +1645,11 @@ export function promise<T>(inner: Schema<T>, message?: string) {
                             buf,
                             pos: 1,
                             createWriteStream: ctx.createWriteStream,
+                            runStream: ctx.runStream,
+                            signal: ctx.signal,
                         };
                         ctxWriter(writeCtx);
+                        checkWrittenSize(writeCtx, buf.length);
                         writer(buf);
                         writer(null);
                     }).catch((err) => {
@@ -556,8 +1669,11 @@ export function promise<T>(inner: Schema<T>, message?: string) {
                                 buf,
                                 pos: 1 + err.schema.schema.length,
                                 createWriteStream: ctx.createWriteStream,
+                                runStream: ctx.runStream,
+                                signal: ctx.signal,
                             };
                             ctxWriter(writeCtx);
+                            checkWrittenSize(writeCtx, buf.length);
                             writer(buf);
                             writer(null);
                             return;
@@ -613,8 +1729,9 @@ export function promise<T>(inner: Schema<T>, message?: string) {
                             }
 
                             reject(
-                                new Error(
-                                    "internal: Invalid promise resolution flag",
+                                new DataError(
+                                    `Invalid promise resolution flag ${flag} for stream ${id}`,
+                                    streamCtx.position - 1,
                                 ),
                             );
                         } catch (err) {
@@ -623,8 +1740,8 @@ export function promise<T>(inner: Schema<T>, message?: string) {
                             cleanup(false);
                         }
                     },
-                    () => {
-                        reject(new OutOfDataError());
+                    (err) => {
+                        reject(err);
                     },
                 );
             });
@@ -684,9 +1801,43 @@ export function iterator<T>(elements: Schema<T>, message?: string) {
                     ctx.buf[ctx.pos] = (id >> 8) & 0xff;
                     ctx.buf[ctx.pos + 1] = id & 0xff;
                     ctx.pos += 2;
-                    (async () => {
+                    ctx.runStream(async () => {
+                        const isAsync = Symbol.asyncIterator in data;
+                        const source: Iterator<any> | AsyncIterator<any> =
+                            isAsync
+                                ? (data as any)[Symbol.asyncIterator]()
+                                : (data as any)[Symbol.iterator]();
+                        let finished = false;
+
+                        // Resolves when aborted, so a source that never yields can still be left.
+                        let onAbort = () => {};
+                        const aborted = new Promise<null>((resolve) => {
+                            onAbort = () => resolve(null);
+                            ctx.signal?.addEventListener("abort", onAbort, {
+                                once: true,
+                            });
+                        });
                         try {
-                            for await (const item of data as any) {
+                            while (!ctx.signal?.aborted) {
+                                const next = Promise.resolve(source.next());
+                                next.then(
+                                    (result) => {
+                                        if (result.done) finished = true;
+                                    },
+                                    () => {
+                                        finished = true;
+                                    },
+                                );
+                                const result = await Promise.race([
+                                    next,
+                                    aborted,
+                                ]);
+                                if (result === null || result.done) break;
+
+                                // Like for await, values from a sync iterator are awaited.
+                                const item = isAsync
+                                    ? result.value
+                                    : await result.value;
                                 const [size, ctxWriter] =
                                     elements.validateAndMakeWriter(
                                         item,
@@ -698,9 +1849,12 @@ export function iterator<T>(elements: Schema<T>, message?: string) {
                                     buf,
                                     pos: 1,
                                     createWriteStream: ctx.createWriteStream,
+                                    runStream: ctx.runStream,
+                                    signal: ctx.signal,
                                 };
                                 ctxWriter(writeCtx);
-                                writer(buf);
+                                checkWrittenSize(writeCtx, buf.length);
+                                await writer(buf);
                             }
                             const buf = new Uint8Array(1);
                             writer(buf);
@@ -722,16 +1876,28 @@ export function iterator<T>(elements: Schema<T>, message?: string) {
                                     buf,
                                     pos: 1 + err.schema.schema.length,
                                     createWriteStream: ctx.createWriteStream,
+                                    runStream: ctx.runStream,
+                                    signal: ctx.signal,
                                 };
                                 ctxWriter(writeCtx);
+                                checkWrittenSize(writeCtx, buf.length);
                                 writer(buf);
                                 writer(null);
                                 return;
                             }
 
                             throw err;
+                        } finally {
+                            ctx.signal?.removeEventListener("abort", onAbort);
+
+                            // Leaving early closes the source, like a for await loop would.
+                            if (!finished) {
+                                Promise.resolve(source.return?.()).catch(
+                                    () => {},
+                                );
+                            }
                         }
-                    })();
+                    });
                 },
             ];
         },
@@ -787,14 +1953,17 @@ export function iterator<T>(elements: Schema<T>, message?: string) {
                             return;
                         }
 
-                        throw new Error("internal: Invalid iterator flag");
+                        throw new DataError(
+                            `Invalid iterator flag ${flag} for stream ${id}`,
+                            streamCtx.position - 1,
+                        );
                     } catch (err) {
                         promiseStream.reject(err);
                         cleanup(false);
                     }
                 },
-                () => {
-                    promiseStream.reject(new OutOfDataError());
+                (err) => {
+                    promiseStream.reject(err);
                 },
             );
 
@@ -854,16 +2023,164 @@ export function boolean(message?: string) {
             const byte = await ctx.readByte();
             if (byte === 0) return [false];
             if (byte === 1) return [true];
-            throw new Error("internal: Invalid boolean value");
+            throw new DataError(
+                `Invalid boolean value ${byte}`,
+                ctx.position - 1,
+            );
         },
         rejectIfNotSameName("boolean"),
         new Uint8Array([dataType.boolean]),
     );
 }
 
+/**
+ * Creates a schema for arrays of booleans, packed 8 to a byte.
+ * This is the same as array(boolean()) to the user, but is around 8x smaller on the wire
+ * which makes it a good fit for bitmaps and large sets of flags.
+ *
+ * @param message - Optional custom validation error message
+ * @returns Schema for boolean[] values
+ *
+ * @example
+ * ```typescript
+ * const flags = booleanArray(); // [true, false, true] is sent as 2 bytes
+ * ```
+ */
+export function booleanArray(message?: string) {
+    if (!message) message = "Data must be an array of booleans";
+    return base<boolean[]>(
+        "booleanArray",
+        (data) => {
+            if (!Array.isArray(data)) throw new ValidationError(message);
+            for (const item of data) {
+                if (typeof item !== "boolean") {
+                    throw new ValidationError(message);
+                }
+            }
+            const byteLen = Math.ceil(data.length / 8);
+            return [
+                getRollingUintSize(data.length) + byteLen,
+                (ctx: WriteContext) => {
+                    ctx.pos = writeRollingUintNoAlloc(
+                        data.length,
+                        ctx.buf,
+                        ctx.pos,
+                    );
+                    ctx.buf.fill(0, ctx.pos, ctx.pos + byteLen);
+                    for (let i = 0; i < data.length; i++) {
+                        if (data[i]) {
+                            ctx.buf[ctx.pos + (i >> 3)] |= 1 << (i & 7);
+                        }
+                    }
+                    ctx.pos += byteLen;
+                },
+            ];
+        },
+        async (ctx) => {
+            const len = await readRollingUintNoAlloc(ctx);
+            const bytes = await ctx.readBytes(Math.ceil(len / 8));
+            const res: boolean[] = new Array(len);
+            for (let i = 0; i < len; i++) {
+                res[i] = (bytes[i >> 3] & (1 << (i & 7))) !== 0;
+            }
+            return [res];
+        },
+        rejectIfNotSameName("booleanArray"),
+        new Uint8Array([dataType.booleanArray]),
+    );
+}
+
+/**
+ * The element types supported by typedArray().
+ */
+export type TypedArrayKind = keyof typeof typedArrayKinds;
+
+const littleEndianHost = new Uint8Array(new Uint16Array([1]).buffer)[0] === 1;
+
+// Flips each element between big and little endian in place.
+function swapElementBytes(bytes: Uint8Array, elementSize: number) {
+    for (let i = 0; i < bytes.length; i += elementSize) {
+        bytes.subarray(i, i + elementSize).reverse();
+    }
+}
+
+/**
+ * Creates a schema for typed arrays of fixed width numbers.
+ * The elements are written as one block of little endian bytes rather than one at a time,
+ * which makes large arrays of numbers much faster to write and read than array(float()).
+ * Use uint8array() for arrays of bytes.
+ *
+ * @template K - The element type
+ * @param kind - The element type, such as "float64" for a Float64Array
+ * @param message - Optional custom validation error message
+ * @returns Schema for typed arrays of the given element type
+ *
+ * @example
+ * ```typescript
+ * const samples = typedArray("float32"); // Float32Array
+ * const heights = typedArray("uint16"); // Uint16Array
+ * ```
+ */
+export function typedArray<K extends TypedArrayKind>(
+    kind: K,
+    message?: string,
+) {
+    const ctor = typedArrayKinds[kind] as any;
+    if (!ctor) throw new Error(`Unknown typed array kind: ${kind}`);
+    if (!message) message = `Data must be a ${ctor.name}`;
+    const elementSize: number = ctor.BYTES_PER_ELEMENT;
+
+    return base<InstanceType<(typeof typedArrayKinds)[K]>>(
+        "typedArray",
+        (data) => {
+            if (!(data instanceof ctor)) throw new ValidationError(message);
+            const byteLen = data.length * elementSize;
+            return [
+                getRollingUintSize(data.length) + byteLen,
+                (ctx: WriteContext) => {
+                    ctx.pos = writeRollingUintNoAlloc(
+                        data.length,
+                        ctx.buf,
+                        ctx.pos,
+                    );
+                    ctx.buf.set(
+                        new Uint8Array(data.buffer, data.byteOffset, byteLen),
+                        ctx.pos,
+                    );
+                    if (!littleEndianHost) {
+                        swapElementBytes(
+                            ctx.buf.subarray(ctx.pos, ctx.pos + byteLen),
+                            elementSize,
+                        );
+                    }
+                    ctx.pos += byteLen;
+                },
+            ];
+        },
+        async (ctx) => {
+            const len = await readRollingUintNoAlloc(ctx);
+            let bytes = await ctx.readBytes(len * elementSize);
+            // With borrowBytes this is a view into the caller's input, which may not be aligned
+            // for the element size and mustn't be changed, so copy it when either matters.
+            if (
+                bytes.byteOffset % elementSize !== 0 ||
+                (!littleEndianHost && ctx.borrowBytes)
+            ) {
+                bytes = bytes.slice();
+            }
+            if (!littleEndianHost) swapElementBytes(bytes, elementSize);
+            return [new ctor(bytes.buffer, bytes.byteOffset, len)];
+        },
+        (other) => other.name === "typedArray" && other._extraInfo === kind,
+        new Uint8Array([dataType.typedArray, typedArrayKindIds.indexOf(kind)]),
+        kind,
+    );
+}
+
 /**
  * Creates a schema for unsigned 8-bit integers (0-255).
  * Validates that data is an integer within the uint8 range and encodes it as a single byte.
+ * Data written with this can be read by a uint(), so a field can be widened later on.
  *
  * @param message - Optional custom validation error message
  * @returns Schema for uint8 values
@@ -898,7 +2215,7 @@ export function uint8(message?: string) {
             const byte = await ctx.readByte();
             return [byte];
         },
-        rejectIfNotSameName("uint8"),
+        (other) => other.name === "uint8" || other.name === "uint",
         new Uint8Array([dataType.uint8]),
     );
 }
@@ -945,16 +2262,97 @@ export function uint(message?: string) {
     );
 }
 
+function matchUnionOption(
+    options: Schema<any>[],
+    data: unknown,
+): [number, ValidationError[]] {
+    const errors: ValidationError[] = [];
+    for (let i = 0; i < options.length; i++) {
+        try {
+            options[i].validateAndMakeWriter(data, {});
+            return [i, errors];
+        } catch (err) {
+            if (err instanceof ValidationError) {
+                errors.push(err);
+            } else {
+                throw err;
+            }
+        }
+    }
+    return [-1, errors];
+}
+
+// These only keep the fields they know about, and drop the rest without an error.
+const fieldDroppingSchemas = new Set([
+    "object",
+    "orderedObject",
+    "sparseObject",
+    "partial",
+]);
+
+// A value that matches an earlier object option can lose fields a later option would have kept,
+// so it would be read back differently than it was written. Rather than doing that silently,
+// make it an error.
+function rejectLossyUnionMatch(
+    options: Schema<any>[],
+    idx: number,
+    data: unknown,
+) {
+    const chosen = options[idx];
+    if (
+        !fieldDroppingSchemas.has(chosen.name) ||
+        typeof data !== "object" ||
+        data === null
+    ) {
+        return;
+    }
+    const dropped = Object.keys(data).filter(
+        (key) =>
+            (data as any)[key] !== undefined &&
+            !Object.prototype.hasOwnProperty.call(chosen._extraInfo, key),
+    );
+    if (dropped.length === 0) return;
+
+    for (let i = idx + 1; i < options.length; i++) {
+        const option = options[i];
+        if (!fieldDroppingSchemas.has(option.name)) continue;
+        const kept = dropped.find((key) =>
+            Object.prototype.hasOwnProperty.call(option._extraInfo, key),
+        );
+        if (kept === undefined) continue;
+        try {
+            option.validateAndMakeWriter(data, {});
+        } catch (err) {
+            if (err instanceof ValidationError) continue;
+            throw err;
+        }
+        throw new ValidationError(
+            `Data matches union options ${idx} and ${i}, but option ${idx} would drop the field ${describeKey(kept)} that option ${i} keeps. ` +
+                "Put the option with more fields first, or use taggedUnion()",
+        );
+    }
+}
+
 /**
  * Creates a schema for union types that can match one of several possible schemas.
  * Attempts to validate against each schema in order until one succeeds.
  * The first matching schema is used for serialization/deserialization.
  *
+ * When options overlap, order decides which one is used. For example, 5 is written as a uint
+ * by union(uint(), int()) but as an int by union(int(), uint()), and null always matches the
+ * first nullable() option. Both sides read the same index back, so for options like these only
+ * the encoding changes. The value itself can change when the chosen option keeps less of it,
+ * such as union(object({ a: uint() }), object({ a: uint(), b: uint() })) dropping b. When a
+ * later object option would have kept a field that the chosen one drops, this is a
+ * ValidationError instead. Put the most specific options first, or use taggedUnion() if options
+ * with the same shape need to stay apart.
+ *
  * @template Schema1 - The first schema type
  * @template OtherSchemas - Array of additional schema types
  * @param first - The first schema to try
  * @param others - Additional schemas to try if the first fails
  * @returns Schema for union of all provided schema types
+ * @throws {Error} If no options are given
  *
  * @example
  * ```typescript
@@ -969,6 +2367,8 @@ export function union<
     Schema1 extends Schema<any>,
     OtherSchemas extends Schema<any>[],
 >(first: Schema1, ...others: OtherSchemas) {
+    // The types need at least one option, but untyped callers can still pass none.
+    if (!first) throw new Error("Unions need at least one option");
     others.unshift(first);
 
     let schemaLen = 1 + getRollingUintSize(others.length - 1); // 1 byte for dataType, plus index size
@@ -993,27 +2393,14 @@ export function union<
     >(
         "union",
         (data, scratchPad) => {
-            const errors: ValidationError[] = [];
-            let idx = -1;
-            for (let i = 0; i < others.length; i++) {
-                try {
-                    others[i].validateAndMakeWriter(data, {});
-                    idx = i;
-                    break;
-                } catch (err) {
-                    if (err instanceof ValidationError) {
-                        errors.push(err);
-                    } else {
-                        throw err;
-                    }
-                }
-            }
+            const [idx, errors] = matchUnionOption(others, data);
             if (idx === -1) {
                 // If we reach here, none matched.
                 throw new ValidationError(
                     `Data did not match any schema in union: ${errors.map((e) => e.message).join("; ")}`,
                 );
             }
+            rejectLossyUnionMatch(others, idx, data);
             const [size, writer] = others[idx].validateAndMakeWriter(
                 data,
                 scratchPad,
@@ -1026,10 +2413,14 @@ export function union<
                 },
             ];
         },
-        async (ctx, hijackReadContext, scratchPad) => {
+        nested(async (ctx, hijackReadContext, scratchPad) => {
+            const start = ctx.position;
             const index = await readRollingUintNoAlloc(ctx);
             if (index < 0 || index >= others.length) {
-                throw new Error("internal: Invalid union schema index");
+                throw new DataError(
+                    `Invalid union schema index ${index} for ${others.length} options`,
+                    start,
+                );
             }
             const value = await others[index].readFromContext(
                 ctx,
@@ -1037,11 +2428,12 @@ export function union<
                 scratchPad,
             );
             return value as any;
-        },
+        }),
         (other) => {
             if (other.name !== "union") return false;
+            // Readers can add options to the end, since these values never use them.
             const otherSchemas = other._extraInfo as Schema<any>[];
-            if (otherSchemas.length !== others.length) return false;
+            if (otherSchemas.length < others.length) return false;
             for (let i = 0; i < others.length; i++) {
                 if (!others[i].isCompatibleWith(otherSchemas[i])) {
                     return false;
@@ -1054,27 +2446,166 @@ export function union<
     );
 }
 
+/**
+ * Gets the index of the option in a union schema that data would be serialized with.
+ * Like serialization, this is the first option that the data validates against.
+ *
+ * @param schema - The union schema to check against
+ * @param data - The data to find the option for
+ * @returns The index of the matching option, or undefined if no option matches
+ *
+ * @example
+ * ```typescript
+ * const stringOrNumber = union(string(), uint());
+ * unionOptionIndex(stringOrNumber, 5); // 1
+ * unionOptionIndex(stringOrNumber, true); // undefined
+ * ```
+ */
+export function unionOptionIndex(
+    schema: Schema<any>,
+    data: unknown,
+): number | undefined {
+    if (schema.name !== "union") {
+        throw new Error("Schema is not a union");
+    }
+    const [idx] = matchUnionOption(schema._extraInfo as Schema<any>[], data);
+    return idx === -1 ? undefined : idx;
+}
+
+/**
+ * The value type of a taggedUnion() schema, where tag is the name of the variant.
+ */
+export type TaggedUnionValue<T extends ObjectSchemas> = {
+    [K in keyof T & string]: { tag: K; value: output<T[K]> };
+}[keyof T & string];
+
+/**
+ * Creates a schema for unions where each variant has a name. Values are written as
+ * `{ tag, value }`, where tag picks the variant and value is checked against that variant's
+ * schema only. Unlike union(), variants with the same shape (such as two strings) stay apart.
+ * Variants are sorted alphabetically, like the fields of object(), and the index of the variant
+ * is written before the value.
+ *
+ * @template T - Object mapping variant names to schemas
+ * @param variants - The schema for each variant
+ * @param message - Optional custom validation error message
+ * @returns Schema for tagged values of any of the variants
+ *
+ * @example
+ * ```typescript
+ * const id = taggedUnion({ email: string(), username: string() });
+ * // { tag: "email", value: "john@example.com" } | { tag: "username", value: "john" }
+ * ```
+ */
+export function taggedUnion<T extends ObjectSchemas>(
+    variants: T,
+    message?: string,
+) {
+    if (!message) message = "Data must be an object with a tag and a value";
+
+    const tags = Object.keys(variants).sort(compareCodeUnits);
+    if (tags.length === 0) {
+        throw new Error("Tagged unions need at least one variant");
+    }
+    const schema = makeObjectSchemaBytes(dataType.taggedUnion, tags, variants);
+
+    return base<TaggedUnionValue<T>>(
+        "taggedUnion",
+        (data, scratchPad) => {
+            if (typeof data !== "object" || data === null) {
+                throw new ValidationError(message);
+            }
+            const tag = (data as any).tag;
+            const idx = typeof tag === "string" ? tags.indexOf(tag) : -1;
+            if (idx === -1) {
+                throw new ValidationError(
+                    `Tag ${JSON.stringify(tag)} is not a variant of the tagged union`,
+                );
+            }
+            const [size, writer] = validateAt(
+                tag,
+                variants[tag],
+                (data as any).value,
+                scratchPad,
+            );
+            return [
+                getRollingUintSize(idx) + size,
+                (ctx: WriteContext) => {
+                    ctx.pos = writeRollingUintNoAlloc(idx, ctx.buf, ctx.pos);
+                    writer(ctx);
+                },
+            ];
+        },
+        async (ctx, hijackReadContext, scratchPad) => {
+            const start = ctx.position;
+            const index = await readRollingUintNoAlloc(ctx);
+            if (index >= tags.length) {
+                throw new DataError(
+                    `Invalid tagged union index ${index} for ${tags.length} variants`,
+                    start,
+                );
+            }
+            const tag = tags[index];
+            const [value] = await variants[tag].readFromContext(
+                ctx,
+                hijackReadContext,
+                scratchPad,
+            );
+            return [{ tag, value } as TaggedUnionValue<T>];
+        },
+        (other) => {
+            if (other.name !== "taggedUnion") return false;
+            const otherVariants = other._extraInfo as ObjectSchemas;
+            const otherTags = Object.keys(otherVariants);
+            if (otherTags.length !== tags.length) return false;
+            for (const tag of tags) {
+                const otherVariant = otherVariants[tag];
+                if (
+                    !otherVariant ||
+                    !variants[tag].isCompatibleWith(otherVariant)
+                ) {
+                    return false;
+                }
+            }
+            return true;
+        },
+        schema,
+        variants,
+    );
+}
+
+// Matches what Date.prototype.toISOString() writes, including the extended year form.
+const isoDateRegex =
+    /^(\d{4}|[+-]\d{6})-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:\d{2})$/;
+
 /**
  * Creates a schema for Date objects.
- * Validates that data is a Date instance and serializes it as an ISO string.
+ * Validates that data is a valid Date instance and serializes it as an ISO string.
  * Preserves full date/time precision including milliseconds and timezone.
  *
+ * By default, any string received is passed to the Date constructor, which may result in an
+ * invalid date. In strict mode, anything other than a full ISO 8601 date and time is rejected
+ * when reading.
+ *
  * @param message - Optional custom validation error message
+ * @param strict - If true, dates that aren't well formed ISO strings are rejected when reading
  * @returns Schema for Date values
  *
  * @example
  * ```typescript
  * const timestamp = date("Expected a valid date");
- * const createdAt = date();
+ * const createdAt = date(undefined, true);
  * ```
  */
-export function date(message?: string) {
+export function date(message?: string, strict = false) {
     if (!message) message = "Data must be a Date";
 
     return base<Date>(
         "date",
         (data) => {
-            if (!(data instanceof Date)) throw new ValidationError(message);
+            if (!(data instanceof Date) || isNaN(data.getTime())) {
+                throw new ValidationError(message);
+            }
             const timeStr = data.toISOString();
             const len = getEncodedLenNoAlloc(timeStr);
             return [
@@ -1090,16 +2621,64 @@ export function date(message?: string) {
             ];
         },
         async (ctx) => {
+            const start = ctx.position;
             const len = await readRollingUintNoAlloc(ctx);
             const bytes = await ctx.readBytes(len);
             const timeStr = td.decode(bytes);
-            return [new Date(timeStr)];
+            const res = new Date(timeStr);
+            if (
+                strict &&
+                (!isoDateRegex.test(timeStr) || isNaN(res.getTime()))
+            ) {
+                throw new DataError(`Invalid ISO 8601 date: ${timeStr}`, start);
+            }
+            return [res];
         },
         rejectIfNotSameName("date"),
         new Uint8Array([dataType.date]),
     );
 }
 
+/**
+ * Creates a schema for Date objects that are written as the number of milliseconds since the
+ * Unix epoch, rather than as an ISO string like date(). Present day dates take 9 bytes rather
+ * than the 24 characters of the ISO string, and need no parsing when read. Like date(), the
+ * timezone isn't kept.
+ *
+ * @param message - Optional custom validation error message
+ * @returns Schema for Date values
+ *
+ * @example
+ * ```typescript
+ * const createdAt = timestamp();
+ * ```
+ */
+export function timestamp(message?: string) {
+    if (!message) message = "Data must be a Date";
+
+    return base<Date>(
+        "timestamp",
+        (data) => {
+            if (!(data instanceof Date) || isNaN(data.getTime())) {
+                throw new ValidationError(message);
+            }
+            const ms = data.getTime();
+            return [
+                getRollingIntSize(ms),
+                (ctx: WriteContext) => {
+                    ctx.pos = writeRollingIntNoAlloc(ms, ctx.buf, ctx.pos);
+                },
+            ];
+        },
+        async (ctx) => {
+            const ms = await readRollingIntNoAlloc(ctx);
+            return [new Date(ms)];
+        },
+        rejectIfNotSameName("timestamp"),
+        new Uint8Array([dataType.timestamp]),
+    );
+}
+
 /**
  * Creates a schema for signed integers (positive and negative integers).
  * Uses zigzag encoding to efficiently represent both positive and negative numbers.
@@ -1122,23 +2701,15 @@ export function int(message?: string) {
             if (typeof data !== "number" || !Number.isInteger(data)) {
                 throw new ValidationError(message);
             }
-            // Zigzag encoding
-            const zigzagged = (data << 1) ^ (data >> 31);
             return [
-                getRollingUintSize(zigzagged),
+                getRollingIntSize(data),
                 (ctx: WriteContext) => {
-                    ctx.pos = writeRollingUintNoAlloc(
-                        zigzagged,
-                        ctx.buf,
-                        ctx.pos,
-                    );
+                    ctx.pos = writeRollingIntNoAlloc(data, ctx.buf, ctx.pos);
                 },
             ];
         },
         async (ctx) => {
-            const zigzagged = await readRollingUintNoAlloc(ctx);
-            // Decode zigzag encoding
-            const value = (zigzagged >>> 1) ^ -(zigzagged & 1);
+            const value = await readRollingIntNoAlloc(ctx);
             return [value];
         },
         rejectIfNotSameName("int"),
@@ -1146,28 +2717,52 @@ export function int(message?: string) {
     );
 }
 
+// The little endian bytes of the quiet NaN most engines use. Engines are free to write any NaN,
+// so canonical() writes these instead.
+const canonicalNaN64 = new Uint8Array([0, 0, 0, 0, 0, 0, 0xf8, 0x7f]);
+const canonicalNaN32 = new Uint8Array([0, 0, 0xc0, 0x7f]);
+
+function writeFixedBytes(
+    bytes: Uint8Array,
+): [number, (ctx: WriteContext) => void] {
+    return [
+        bytes.length,
+        (ctx: WriteContext) => {
+            ctx.buf.set(bytes, ctx.pos);
+            ctx.pos += bytes.length;
+        },
+    ];
+}
+
 /**
  * Creates a schema for floating-point numbers (including integers as floats).
  * Uses IEEE 754 double precision (64-bit) encoding for full precision.
- * Accepts any JavaScript number including Infinity, -Infinity, and NaN.
+ * Accepts any JavaScript number including Infinity, -Infinity, and NaN, unless finite is set.
+ * In finite mode, those are rejected when writing and when reading, which is useful when the
+ * other side can't represent them (such as JSON).
  *
  * @param message - Optional custom validation error message
+ * @param finite - If true, Infinity, -Infinity, and NaN are rejected
  * @returns Schema for floating-point number values
  *
  * @example
  * ```typescript
  * const price = float("Price must be a number");
  * const ratio = float();
+ * const score = float(undefined, true); // no NaN or Infinity
  * ```
  */
-export function float(message?: string) {
+export function float(message?: string, finite = false) {
     if (!message) message = "Data must be a float";
     return base<number>(
         "float",
-        (data) => {
-            if (typeof data !== "number") {
+        (data, scratchPad) => {
+            if (typeof data !== "number" || (finite && !isFinite(data))) {
                 throw new ValidationError(message);
             }
+            if (isNaN(data) && scratchPad[canonicalKey] === true) {
+                return writeFixedBytes(canonicalNaN64);
+            }
             return [
                 8,
                 (ctx: WriteContext) => {
@@ -1185,6 +2780,12 @@ export function float(message?: string) {
             const bytes = await ctx.readBytes(8);
             const view = new DataView(bytes.buffer, bytes.byteOffset, 8);
             const value = view.getFloat64(0, true);
+            if (finite && !isFinite(value)) {
+                throw new DataError(
+                    `Received non-finite float ${value}`,
+                    ctx.position - 8,
+                );
+            }
             return [value];
         },
         rejectIfNotSameName("float"),
@@ -1192,6 +2793,70 @@ export function float(message?: string) {
     );
 }
 
+/**
+ * Creates a schema for single precision floating-point numbers.
+ * Uses IEEE 754 single precision (32-bit) encoding, which is half the size of float().
+ * Only numbers that can be stored exactly in 32 bits are accepted (including Infinity,
+ * -Infinity, and NaN), so precision is never lost without it being noticed. Use Math.fround()
+ * to round a number first if losing precision is fine. Like float(), finite mode rejects
+ * Infinity, -Infinity, and NaN.
+ *
+ * @param message - Optional custom validation error message
+ * @param finite - If true, Infinity, -Infinity, and NaN are rejected
+ * @returns Schema for single precision number values
+ *
+ * @example
+ * ```typescript
+ * const volume = float32();
+ * volume.validateAndMakeWriter(0.5, {}); // ok
+ * volume.validateAndMakeWriter(0.1, {}); // throws, use Math.fround(0.1)
+ * ```
+ */
+export function float32(message?: string, finite = false) {
+    if (!message) message = "Data must be a 32-bit float";
+    return base<number>(
+        "float32",
+        (data, scratchPad) => {
+            if (
+                typeof data !== "number" ||
+                (Math.fround(data) !== data && !isNaN(data)) ||
+                (finite && !isFinite(data))
+            ) {
+                throw new ValidationError(message);
+            }
+            if (isNaN(data) && scratchPad[canonicalKey] === true) {
+                return writeFixedBytes(canonicalNaN32);
+            }
+            return [
+                4,
+                (ctx: WriteContext) => {
+                    const view = new DataView(
+                        ctx.buf.buffer,
+                        ctx.buf.byteOffset + ctx.pos,
+                        4,
+                    );
+                    view.setFloat32(0, data, true);
+                    ctx.pos += 4;
+                },
+            ];
+        },
+        async (ctx) => {
+            const bytes = await ctx.readBytes(4);
+            const view = new DataView(bytes.buffer, bytes.byteOffset, 4);
+            const value = view.getFloat32(0, true);
+            if (finite && !isFinite(value)) {
+                throw new DataError(
+                    `Received non-finite float ${value}`,
+                    ctx.position - 4,
+                );
+            }
+            return [value];
+        },
+        rejectIfNotSameName("float32"),
+        new Uint8Array([dataType.float32]),
+    );
+}
+
 /**
  * Creates a schema for nullable values (T | null).
  * If no inner schema is provided, only accepts null values.
@@ -1245,8 +2910,10 @@ export function nullable<T = null>(inner?: Schema<T>) {
             }
             if (flag === 1) {
                 if (!inner) {
-                    throw new Error(
-                        "internal: No inner schema provided for nullable",
+                    // The writer's schema would have rejected this, so the data is bad.
+                    throw new DataError(
+                        "Received a value for a nullable that can only be null",
+                        ctx.position - 1,
                     );
                 }
                 const value = await inner.readFromContext(
@@ -1256,7 +2923,10 @@ export function nullable<T = null>(inner?: Schema<T>) {
                 );
                 return value as [T];
             }
-            throw new Error("internal: Invalid nullable flag");
+            throw new DataError(
+                `Invalid nullable flag ${flag}`,
+                ctx.position - 1,
+            );
         },
         (other) => {
             if (other.name !== "nullable") return false;
@@ -1325,7 +2995,10 @@ export function optional<T>(inner: Schema<T>) {
                 );
                 return value as [T];
             }
-            throw new Error("internal: Invalid optional flag");
+            throw new DataError(
+                `Invalid optional flag ${flag}`,
+                ctx.position - 1,
+            );
         },
         (other) => {
             if (other.name !== "optional") return false;
@@ -1339,7 +3012,7 @@ export function optional<T>(inner: Schema<T>) {
 /**
  * Creates a schema for BigInt values.
  * Validates that data is a bigint and serializes it as a 64-bit unsigned integer.
- * Supports values from 0 to 2^64-1.
+ * Supports values from 0 to 2^64-1. Use arbitraryBigint() for values outside of this range.
  *
  * @param message - Optional custom validation error message
  * @returns Schema for bigint values
@@ -1355,7 +3028,12 @@ export function bigint(message?: string) {
     return base<bigint>(
         "bigint",
         (data) => {
-            if (typeof data !== "bigint") {
+            // Anything outside of the range would silently wrap around when written.
+            if (
+                typeof data !== "bigint" ||
+                data < 0n ||
+                data > 0xffffffffffffffffn
+            ) {
                 throw new ValidationError(message);
             }
             return [
@@ -1382,6 +3060,140 @@ export function bigint(message?: string) {
     );
 }
 
+function bigint128(name: string, signed: boolean, message: string) {
+    const min = signed ? -(2n ** 127n) : 0n;
+    const max = signed ? 2n ** 127n - 1n : 2n ** 128n - 1n;
+    return base<bigint>(
+        name,
+        (data) => {
+            if (typeof data !== "bigint" || data < min || data > max) {
+                throw new ValidationError(message);
+            }
+            return [
+                16,
+                (ctx: WriteContext) => {
+                    const view = new DataView(
+                        ctx.buf.buffer,
+                        ctx.buf.byteOffset + ctx.pos,
+                        16,
+                    );
+                    const unsigned = BigInt.asUintN(128, data);
+                    view.setBigUint64(0, BigInt.asUintN(64, unsigned), true);
+                    view.setBigUint64(8, unsigned >> 64n, true);
+                    ctx.pos += 16;
+                },
+            ];
+        },
+        async (ctx) => {
+            const bytes = await ctx.readBytes(16);
+            const view = new DataView(bytes.buffer, bytes.byteOffset, 16);
+            const unsigned =
+                view.getBigUint64(0, true) |
+                (view.getBigUint64(8, true) << 64n);
+            return [signed ? BigInt.asIntN(128, unsigned) : unsigned];
+        },
+        rejectIfNotSameName(name),
+        new Uint8Array([signed ? dataType.int128 : dataType.uint128]),
+    );
+}
+
+/**
+ * Creates a schema for signed 128-bit integers, represented as a bigint.
+ * Always encoded as 16 bytes (little endian, two's complement).
+ *
+ * @param message - Optional custom validation error message
+ * @returns Schema for bigint values between -(2^127) and 2^127-1
+ *
+ * @example
+ * ```typescript
+ * const balance = int128();
+ * ```
+ */
+export function int128(message?: string) {
+    if (!message) message = "Data must be a bigint in the int128 range";
+    return bigint128("int128", true, message);
+}
+
+/**
+ * Creates a schema for unsigned 128-bit integers, represented as a bigint.
+ * Always encoded as 16 bytes (little endian).
+ *
+ * @param message - Optional custom validation error message
+ * @returns Schema for bigint values between 0 and 2^128-1
+ *
+ * @example
+ * ```typescript
+ * const uuid = uint128();
+ * ```
+ */
+export function uint128(message?: string) {
+    if (!message) message = "Data must be a bigint in the uint128 range";
+    return bigint128("uint128", false, message);
+}
+
+/**
+ * Creates a schema for BigInt values of any size, positive or negative.
+ * Encoded as a sign byte, followed by the length of the magnitude and the magnitude itself
+ * in little endian bytes, so small values stay small on the wire.
+ *
+ * @param message - Optional custom validation error message
+ * @returns Schema for bigint values
+ *
+ * @example
+ * ```typescript
+ * const huge = arbitraryBigint(); // 2n ** 200n and -12345678901234567890n both work
+ * ```
+ */
+export function arbitraryBigint(message?: string) {
+    if (!message) message = "Data must be a bigint";
+    return base<bigint>(
+        "arbitraryBigint",
+        (data) => {
+            if (typeof data !== "bigint") {
+                throw new ValidationError(message);
+            }
+            const negative = data < 0n;
+            let magnitude = negative ? -data : data;
+            const bytes: number[] = [];
+            while (magnitude > 0n) {
+                bytes.push(Number(magnitude & 0xffn));
+                magnitude >>= 8n;
+            }
+            return [
+                1 + getRollingUintSize(bytes.length) + bytes.length,
+                (ctx: WriteContext) => {
+                    ctx.buf[ctx.pos++] = negative ? 1 : 0;
+                    ctx.pos = writeRollingUintNoAlloc(
+                        bytes.length,
+                        ctx.buf,
+                        ctx.pos,
+                    );
+                    ctx.buf.set(bytes, ctx.pos);
+                    ctx.pos += bytes.length;
+                },
+            ];
+        },
+        async (ctx) => {
+            const sign = await ctx.readByte();
+            if (sign > 1) {
+                throw new DataError(
+                    `Invalid bigint sign ${sign}`,
+                    ctx.position - 1,
+                );
+            }
+            const len = await readRollingUintNoAlloc(ctx);
+            const bytes = await ctx.readBytes(len);
+            let magnitude = 0n;
+            for (let i = len - 1; i >= 0; i--) {
+                magnitude = (magnitude << 8n) | BigInt(bytes[i]);
+            }
+            return [sign === 1 ? -magnitude : magnitude];
+        },
+        rejectIfNotSameName("arbitraryBigint"),
+        new Uint8Array([dataType.arbitraryBigint]),
+    );
+}
+
 /**
  * Creates a schema for ReadableStream<Uint8Array> objects.
  * Handles streaming binary data by creating a stream channel for the readable stream.
@@ -1412,12 +3224,24 @@ export function readableStream(message?: string) {
                     ctx.buf[ctx.pos] = (id >> 8) & 0xff;
                     ctx.buf[ctx.pos + 1] = id & 0xff;
                     ctx.pos += 2;
-                    (async () => {
+                    ctx.runStream(async () => {
                         try {
                             const reader = data.getReader();
+                            const cancel = () => {
+                                reader
+                                    .cancel(ctx.signal!.reason)
+                                    .catch(() => {});
+                            };
+                            ctx.signal?.addEventListener("abort", cancel, {
+                                once: true,
+                            });
                             for (;;) {
                                 const { done, value } = await reader.read();
-                                if (done) {
+                                if (done || ctx.signal?.aborted) {
+                                    ctx.signal?.removeEventListener(
+                                        "abort",
+                                        cancel,
+                                    );
                                     writer(new Uint8Array(1));
                                     writer(null);
                                     break;
@@ -1436,12 +3260,12 @@ export function readableStream(message?: string) {
                                     0,
                                 );
                                 arr.set(value as Uint8Array, pos);
-                                writer(arr);
+                                await writer(arr);
                             }
                         } catch (err) {
                             throw err;
                         }
-                    })();
+                    });
                 },
             ];
         },
@@ -1471,8 +3295,8 @@ export function readableStream(message?: string) {
                                 cleanup(false);
                             }
                         },
-                        () => {
-                            controller.error(new OutOfDataError());
+                        (err) => {
+                            controller.error(err);
                         },
                     );
                 },
@@ -1490,7 +3314,8 @@ export function readableStream(message?: string) {
 /**
  * Creates a schema for record objects (objects with string keys and values of a specific type).
  * Similar to object() but for dynamic key-value pairs where all values have the same schema.
- * Only includes enumerable own properties of the object.
+ * Only includes enumerable own properties of the object. Keys are written in sorted order, so
+ * the same record always serializes to the same bytes.
  *
  * @template S - The schema type for values
  * @param child - Schema for all values in the record
@@ -1520,9 +3345,11 @@ export function record<S extends Schema<any>>(
                 throw new ValidationError(message);
             }
             const writers: ((ctx: WriteContext) => void)[] = [];
-            const keys = Object.keys(data).filter((k) =>
-                Object.prototype.hasOwnProperty.call(data, k),
-            );
+            // Sorted so the same entries always give the same bytes, whatever order
+            // they were added in.
+            const keys = Object.keys(data)
+                .filter((k) => Object.prototype.hasOwnProperty.call(data, k))
+                .sort(compareCodeUnits);
             let size = getRollingUintSize(keys.length);
             for (const key of keys) {
                 const keyLen = getEncodedLenNoAlloc(key);
@@ -1532,7 +3359,9 @@ export function record<S extends Schema<any>>(
                         "Record keys cannot be __proto__ or constructor",
                     );
                 }
-                const [s, writer] = child.validateAndMakeWriter(
+                const [s, writer] = validateAt(
+                    describeKey(key),
+                    child,
                     (data as any)[key],
                     scratchPad,
                 );
@@ -1590,6 +3419,145 @@ export function record<S extends Schema<any>>(
     );
 }
 
+function recordKeyFor(key: string, keySchema: Schema<any>) {
+    try {
+        keySchema.validateAndMakeWriter(key, {});
+        return key;
+    } catch (err) {
+        if (!(err instanceof ValidationError)) throw err;
+    }
+
+    // Object keys are always strings, so try it as the number it was set as.
+    const num = Number(key);
+    if (key !== "" && String(num) === key) {
+        return num;
+    }
+    throw new ValidationError(`Record key ${key} does not match key schema`);
+}
+
+/**
+ * Creates a schema for record objects where the keys are encoded with their own schema.
+ * Unlike record(), keys do not have to be strings on the wire. Since object keys are always
+ * strings in JavaScript, keys that look like numbers are validated as numbers when the key
+ * schema does not accept strings, and keys are turned back into strings when read. Like
+ * record(), entries are written sorted by key.
+ *
+ * @template K - The type of the record keys
+ * @template V - The type of the record values
+ * @param keySchema - Schema for the record keys
+ * @param valueSchema - Schema for the record values
+ * @param message - Optional custom validation error message
+ * @returns Schema for Record<K, V> values
+ *
+ * @example
+ * ```typescript
+ * const scoresById = dynRecord(uint(), string()); // Record<number, string>
+ * ```
+ */
+export function dynRecord<K extends string | number, V>(
+    keySchema: Schema<K>,
+    valueSchema: Schema<V>,
+    message?: string,
+) {
+    if (!message) message = "Data must be a record (object with keys)";
+
+    return base<Record<K, V>>(
+        "dynRecord",
+        (data, scratchPad) => {
+            if (
+                typeof data !== "object" ||
+                data === null ||
+                Array.isArray(data)
+            ) {
+                throw new ValidationError(message);
+            }
+            const writers: ((ctx: WriteContext) => void)[] = [];
+            const keys = Object.keys(data).sort(compareCodeUnits);
+            let size = getRollingUintSize(keys.length);
+            for (const key of keys) {
+                if (key === "__proto__" || key === "constructor") {
+                    throw new ValidationError(
+                        "Record keys cannot be __proto__ or constructor",
+                    );
+                }
+                const [keySize, keyWriter] = validateAt(
+                    describeKey(key),
+                    keySchema,
+                    recordKeyFor(key, keySchema),
+                    scratchPad,
+                );
+                const [valueSize, valueWriter] = validateAt(
+                    describeKey(key),
+                    valueSchema,
+                    (data as any)[key],
+                    scratchPad,
+                );
+                size += keySize + valueSize;
+                writers.push((ctx: WriteContext) => {
+                    keyWriter(ctx);
+                    valueWriter(ctx);
+                });
+            }
+            return [
+                size,
+                (ctx: WriteContext) => {
+                    ctx.pos = writeRollingUintNoAlloc(
+                        keys.length,
+                        ctx.buf,
+                        ctx.pos,
+                    );
+                    for (const writer of writers) {
+                        writer(ctx);
+                    }
+                },
+            ];
+        },
+        async (ctx, hijackReadContext, scratchPad) => {
+            const len = await readRollingUintNoAlloc(ctx);
+            const res = {} as Record<K, V>;
+            for (let i = 0; i < len; i++) {
+                const offset = ctx.position;
+                const [key] = await keySchema.readFromContext(
+                    ctx,
+                    hijackReadContext,
+                    scratchPad,
+                );
+                const strKey = String(key);
+                if (strKey === "__proto__" || strKey === "constructor") {
+                    throw new DataError(
+                        "Record keys cannot be __proto__ or constructor",
+                        offset,
+                    );
+                }
+                const value = await valueSchema.readFromContext(
+                    ctx,
+                    hijackReadContext,
+                    scratchPad,
+                );
+                res[key] = value[0];
+            }
+            return [res];
+        },
+        (other) => {
+            if (other.name !== "dynRecord") return false;
+            const [otherKeySchema, otherValueSchema] = other._extraInfo as [
+                Schema<any>,
+                Schema<any>,
+            ];
+            return (
+                keySchema.isCompatibleWith(otherKeySchema) &&
+                valueSchema.isCompatibleWith(otherValueSchema)
+            );
+        },
+        new Uint8Array([
+            dataType.dynRecord,
+            ...keySchema.schema,
+            ...valueSchema.schema,
+        ]),
+        [keySchema, valueSchema],
+    );
+}
+
 /**
  * Creates a schema for Map objects with specific key and value types.
  * Validates that data is a Map instance and that all entries conform to their respective schemas.
@@ -1621,21 +3589,33 @@ export function map<K, V>(
             if (!(data instanceof Map)) {
                 throw new ValidationError(message);
             }
-            const writers: ((ctx: WriteContext) => void)[] = [];
+            const entries: [number, (ctx: WriteContext) => void][] = [];
             let size = getRollingUintSize(data.size);
-            for (const [key, value] of data.entries()) {
-                const [keySize, keyWriter] = keySchema.validateAndMakeWriter(
-                    key,
-                    scratchPad,
-                );
-                const [valueSize, valueWriter] =
-                    valueSchema.validateAndMakeWriter(value, scratchPad);
-                size += keySize + valueSize;
-                writers.push((ctx: WriteContext) => {
-                    keyWriter(ctx);
-                    valueWriter(ctx);
-                });
-            }
+            validateSortedEntries(scratchPad, () => {
+                for (const [key, value] of data.entries()) {
+                    const [keySize, keyWriter] = validateAt(
+                        describeKey(key),
+                        keySchema,
+                        key,
+                        scratchPad,
+                    );
+                    const [valueSize, valueWriter] = validateAt(
+                        describeKey(key),
+                        valueSchema,
+                        value,
+                        scratchPad,
+                    );
+                    size += keySize + valueSize;
+                    entries.push([
+                        keySize + valueSize,
+                        (ctx: WriteContext) => {
+                            keyWriter(ctx);
+                            valueWriter(ctx);
+                        },
+                    ]);
+                }
+            });
+            const sorted = scratchPad[canonicalKey] === true;
             return [
                 size,
                 (ctx: WriteContext) => {
@@ -1644,7 +3624,11 @@ export function map<K, V>(
                         ctx.buf,
                         ctx.pos,
                     );
-                    for (const writer of writers) {
+                    if (sorted) {
+                        writeSortedByBytes(ctx, entries);
+                        return;
+                    }
+                    for (const [, writer] of entries) {
                         writer(ctx);
                     }
                 },
@@ -1873,6 +3857,11 @@ export function compressionTable<T extends Schema<any>>(
     return base<output<T>>(
         "compressionTable",
         (data, scratchPad) => {
+            if (scratchPad[sortingEntriesKey] === true) {
+                throw new Error(
+                    "compressionTable() can't be used inside a Map or Set under canonical()",
+                );
+            }
             const reverseTable = useScratchPadValue(
                 scratchPad,
                 compressionTableKey,
@@ -1946,6 +3935,7 @@ export function compressionTable<T extends Schema<any>>(
                 compressionTableKey,
                 [] as any[],
             );
+            const start = ctx.position;
             const index = await readRollingUintNoAlloc(ctx);
             if (index === 0) {
                 const value = await child.readFromContext(
@@ -1962,7 +3952,10 @@ export function compressionTable<T extends Schema<any>>(
                 if (table.length >= index) {
                     return [undefined as output<T>];
                 }
-                throw new Error("internal: Invalid compression table index");
+                throw new DataError(
+                    `Invalid compression table index ${index} for ${table.length} entries`,
+                    start,
+                );
             }
             if (entry instanceof _CopyProtector) {
                 return [entry.clone() as output<T>];
@@ -2042,14 +4035,20 @@ export function potentiallyFloatString(message?: string) {
     );
 }
 
+// Schemas with the same name can still differ, such as objects with different fields,
+// so reflected schemas are told apart by their bytes.
+function schemaKey(schema: Schema<any>) {
+    return schema.schema.join(",");
+}
+
 function reflectDataToSchema(data: any): Schema<any> {
     if (Array.isArray(data)) {
         const elementSchemas: Schema<any>[] = [];
         const elementSet = new Set<string>();
         for (const element of data) {
             const res = reflectDataToSchema(element);
-            if (!elementSet.has(res.name)) {
-                elementSet.add(res.name);
+            if (!elementSet.has(schemaKey(res))) {
+                elementSet.add(schemaKey(res));
                 elementSchemas.push(res);
             }
         }
@@ -2076,6 +4075,10 @@ function reflectDataToSchema(data: any): Schema<any> {
                 return float();
             }
         case "bigint":
+            // bigint() only covers unsigned 64-bit values.
+            if (data < 0n || data > 0xffffffffffffffffn) {
+                return arbitraryBigint();
+            }
             return bigint();
         case "string":
             return string();
@@ -2094,13 +4097,13 @@ function reflectDataToSchema(data: any): Schema<any> {
                 const valueSet = new Set<string>();
                 for (const [key, value] of data.entries()) {
                     const keyRes = reflectDataToSchema(key);
-                    if (!keySet.has(keyRes.name)) {
-                        keySet.add(keyRes.name);
+                    if (!keySet.has(schemaKey(keyRes))) {
+                        keySet.add(schemaKey(keyRes));
                         keySchemas.push(keyRes);
                     }
                     const valueRes = reflectDataToSchema(value);
-                    if (!valueSet.has(valueRes.name)) {
-                        valueSet.add(valueRes.name);
+                    if (!valueSet.has(schemaKey(valueRes))) {
+                        valueSet.add(schemaKey(valueRes));
                         valueSchemas.push(valueRes);
                     }
                 }
@@ -2146,12 +4149,34 @@ function reflectDataToSchema(data: any): Schema<any> {
             }
             return object(fields);
         default:
-            throw new Error(
-                `internal: Cannot reflect data of unsupported type: ${typeof data}`,
+            // Functions, symbols, and undefined have no wire representation, so this is the
+            // caller passing data any() can't write rather than a bug in the library.
+            throw new ValidationError(
+                `Cannot write data of type ${typeof data} with any()`,
             );
     }
 }
 
+/**
+ * Works out a schema for a value, the same way any() does when writing it. Objects become
+ * object() with their fields sorted, whole numbers become uint() or int(), and other numbers
+ * become float(). Arrays and Maps with more than one shape of element get a union() of them,
+ * and empty ones get any() elements.
+ *
+ * @param data - The value to infer a schema for
+ * @returns A schema that the value validates against
+ * @throws {ValidationError} If the value contains something that can't be written, such as a function
+ *
+ * @example
+ * ```typescript
+ * const value = { name: "John", tags: ["a", "b"] };
+ * await serialize(inferSchema(value), stream, value);
+ * ```
+ */
+export function inferSchema(data: unknown): Schema<any> {
+    return reflectDataToSchema(data);
+}
+
 /**
  * Creates a schema that accepts any supported data type.
  * Uses runtime reflection to determine the appropriate schema for the given data.
@@ -2189,11 +4214,11 @@ export function any(message?: string) {
                 },
             ];
         },
-        async (ctx, hijackReadContext, scratchPad) => {
+        nested(async (ctx, hijackReadContext, scratchPad) => {
             const { reflectByteReprToSchema } = await import("./reflection");
             const schema = await reflectByteReprToSchema(ctx);
             return schema.readFromContext(ctx, hijackReadContext, scratchPad);
-        },
+        }),
         rejectIfNotSameName("any"),
         new Uint8Array([dataType.any]),
     );
@@ -1,7 +1,18 @@
 import type { Writable } from "stream";
-import type { Schema } from "./schemas";
-import type { WriteContext } from "./utils";
+import type { ObjectSchemas, Schema } from "./schemas";
+import {
+    checkWrittenSize,
+    getRollingUintSize,
+    writeRollingUintNoAlloc,
+    type WriteContext,
+} from "./utils";
 import { getHash, output } from "./deserialize";
+import {
+    headerFlags,
+    internedSchemaVersion,
+    versionShift,
+} from "./header";
+import { internSchemaBytes } from "./schemaInterning";
 
 function waitGroup() {
     const promises: Set<Promise<void>> = new Set();
@@ -25,113 +36,275 @@ function waitGroup() {
     };
 }
 
+function streamLimiter(limit?: number) {
+    let active = 0;
+    const queue: (() => Promise<void>)[] = [];
+    const next = () => {
+        while (queue.length > 0 && (limit === undefined || active < limit)) {
+            const task = queue.shift()!;
+            active++;
+            task().finally(() => {
+                active--;
+                next();
+            });
+        }
+    };
+    return (task: () => Promise<void>) => {
+        queue.push(task);
+        next();
+    };
+}
+
+function makeHeader(
+    schema: Schema<any>,
+    lastUpdateIsUs: boolean,
+    options: SerializeOptions,
+) {
+    const schemaBytes = lastUpdateIsUs
+        ? null
+        : options.internSchemas
+          ? internSchemaBytes(schema.schema)
+          : schema.schema;
+    const header = new Uint8Array(1 + (schemaBytes?.length ?? 0));
+    if (schemaBytes) {
+        header[0] |= headerFlags.hasSchema; // We need to send the schema.
+        header.set(schemaBytes, 1);
+    }
+    if (options.sequenceNumbers) {
+        header[0] |= headerFlags.sequenceNumbers;
+    }
+    const version =
+        schemaBytes && options.internSchemas ? internedSchemaVersion : 0;
+    header[0] |= version << versionShift;
+    return header;
+}
+
+function makeFrame(id: number, chunk: Uint8Array, seq: number | null) {
+    const seqSize = seq === null ? 0 : getRollingUintSize(seq);
+    const frame = new Uint8Array(2 + seqSize + chunk.length);
+    frame[0] = (id >> 8) & 0xff;
+    frame[1] = id & 0xff;
+    if (seq !== null) {
+        writeRollingUintNoAlloc(seq, frame, 2);
+    }
+    frame.set(chunk, 2 + seqSize);
+    return frame;
+}
+
+function checkStreamLimit(open: number, options: SerializeOptions) {
+    if (options.maxStreams !== undefined && open >= options.maxStreams) {
+        throw new Error(
+            `Can't have more than ${options.maxStreams} streams open at once`,
+        );
+    }
+}
+
+function sequenceCounter(options: SerializeOptions) {
+    let next = 0;
+    return () => (options.sequenceNumbers ? next++ : null);
+}
+
 async function browserSerialize<Resolved, S extends Schema<Resolved>>(
     schema: S,
     writable: WritableStream,
     data: Resolved,
     lastUpdateIsUs: boolean,
+    options: SerializeOptions,
 ) {
-    // Figure out the size of the schema.
-    let size = 1;
-    if (!lastUpdateIsUs) {
-        size += schema.schema.length;
+    // If we are asked to, send the header before we walk the value.
+    const header = makeHeader(schema, lastUpdateIsUs, options);
+    let headerSize = header.length;
+    const headerWriter = options.writeHeaderFirst ? writable.getWriter() : null;
+    if (headerWriter) {
+        await headerWriter.write(header);
+        headerSize = 0;
     }
-    const [valueSize, writeData] = schema.validateAndMakeWriter(data, {});
-    size += valueSize;
 
-    // Create a buffer of that size and write the header.
-    const buffer = new Uint8Array(size);
-    if (!lastUpdateIsUs) {
-        buffer[0] = 1; // We need to send the schema.
-        buffer.set(schema.schema, 1);
+    // Figure out the size of the data. If it is invalid, hand the stream back unlocked.
+    let sized: [number, (ctx: WriteContext) => void];
+    try {
+        sized = schema.validateAndMakeWriter(data, {});
+    } catch (err) {
+        headerWriter?.releaseLock();
+        throw err;
     }
+    const [valueSize, writeData] = sized;
+    const writer = headerWriter ?? writable.getWriter();
 
-    // Write the data.
-    const writer = writable.getWriter();
+    // Create a buffer of that size and write the header if it isn't sent.
+    const buffer = new Uint8Array(headerSize + valueSize);
+    if (headerSize !== 0) {
+        buffer.set(header, 0);
+    }
 
     // Defines the sender queue.
+    const nextSeq = sequenceCounter(options);
     let socketOpen = true;
     let pendingQueue: [number, Uint8Array | Buffer][] | null = [];
-    let socketId = 1;
-    let connectedCount = 0;
-    const wg = waitGroup();
-    const createWriteStream = () => {
-        const closer = wg.add();
-        const id = socketId++;
-        let connected = true;
-        connectedCount++;
-
-        return [
-            id,
-            (chunk: Uint8Array | Buffer | null) => {
-                if (!socketOpen) return;
-                if (!connected) throw new Error("Stream is closed");
-
-                if (chunk === null) {
-                    connected = false;
-                    connectedCount--;
-                    if (connectedCount === 0) {
-                        pendingQueue = null;
-                    }
-                    closer();
-                    return;
-                }
-
-                if (pendingQueue !== null) {
-                    pendingQueue.push([id, chunk]);
-                    return;
-                }
-
-                if (socketOpen) {
-                    const newAlloc = new Uint8Array(chunk.length + 2);
-                    newAlloc[0] = (id >> 8) & 0xff;
-                    newAlloc[1] = id & 0xff;
-                    newAlloc.set(chunk, 2);
-                    writer.write(newAlloc).catch(() => {
-                        socketOpen = false;
-                    });
-                }
-            },
-        ] as [number, (chunk: Uint8Array | Buffer | null) => void];
+    const onAbort = () => {
+        socketOpen = false;
+        pendingQueue = null;
     };
+    options.signal?.addEventListener("abort", onAbort, { once: true });
+    try {
+        let socketId = 1;
+        let connectedCount = 0;
+        const wg = waitGroup();
+        const createWriteStream = () => {
+            checkStreamLimit(connectedCount, options);
+            const closer = wg.add();
+            const id = socketId++;
+            let connected = true;
+            connectedCount++;
+
+            return [
+                id,
+                (chunk: Uint8Array | Buffer | null) => {
+                    if (!connected) throw new Error("Stream is closed");
+
+                    // Always close, so that a cancelled serialize still finishes.
+                    if (chunk === null) {
+                        connected = false;
+                        connectedCount--;
+                        if (connectedCount === 0) {
+                            pendingQueue = null;
+                        }
+                        closer();
+                        return;
+                    }
+                    if (!socketOpen) return;
 
-    // Write into the context.
-    const baseCtx: WriteContext = {
-        buf: buffer,
-        pos: 1 + (lastUpdateIsUs ? 0 : schema.schema.length),
-        createWriteStream,
-    };
-    writeData(baseCtx);
+                    if (pendingQueue !== null) {
+                        pendingQueue.push([id, chunk]);
+                        return;
+                    }
 
-    // Write the buffer.
-    await writer.write(buffer);
+                    if (socketOpen) {
+                        const frame = makeFrame(id, chunk, nextSeq());
+                        const written = writer.write(frame).catch(() => {
+                            socketOpen = false;
+                        });
+                        if (options.backpressure) return written;
+                    }
+                },
+            ] as [
+                number,
+                (chunk: Uint8Array | Buffer | null) => void | Promise<void>,
+            ];
+        };
+
+        // Write into the context.
+        const baseCtx: WriteContext = {
+            buf: buffer,
+            pos: headerSize,
+            createWriteStream,
+            runStream: streamLimiter(options.maxConcurrentStreams),
+            signal: options.signal,
+        };
+        writeData(baseCtx);
+        checkWrittenSize(baseCtx, buffer.length);
+
+        // Write the buffer. If this fails, stop any streams from queueing more data.
+        await writer.write(buffer).catch((e: any) => {
+            socketOpen = false;
+            pendingQueue = null;
+            throw e;
+        });
 
-    // Flush the pending queue.
-    const pq = pendingQueue;
-    pendingQueue = null;
-    if (pq) {
-        for (const [id, chunk] of pq) {
-            if (!socketOpen) break;
-            const newAlloc = new Uint8Array(chunk.length + 2);
-            newAlloc[0] = (id >> 8) & 0xff;
-            newAlloc[1] = id & 0xff;
-            newAlloc.set(chunk, 2);
-            await writer.write(newAlloc).catch((e: any) => {
-                socketOpen = false;
-                throw e;
-            });
+        // Flush the pending queue.
+        const pq = pendingQueue;
+        pendingQueue = null;
+        if (pq) {
+            for (const [id, chunk] of pq) {
+                if (!socketOpen) break;
+                const frame = makeFrame(id, chunk, nextSeq());
+                await writer.write(frame).catch((e: any) => {
+                    socketOpen = false;
+                    throw e;
+                });
+            }
         }
-    }
 
-    // Wait for all streams to close.
-    await wg.wait();
+        // Wait for all streams to close.
+        await wg.wait();
 
-    // Close the connection.
-    if (socketOpen) {
-        await writer.close().catch(() => {});
+        // Close the connection, or hand it back if the caller is going to write more.
+        if (options.keepOpen) {
+            writer.releaseLock();
+        } else if (socketOpen) {
+            await writer.close().catch(() => {});
+        } else if (options.signal?.aborted) {
+            await writer.abort(options.signal.reason).catch(() => {});
+        }
+    } finally {
+        // The signal can outlive this call, so don't keep everything above alive with it.
+        options.signal?.removeEventListener("abort", onAbort);
     }
 }
 
+/**
+ * Options for {@link serialize}.
+ */
+export type SerializeOptions = {
+    /**
+     * If true, the header is written before the data is validated and measured. This gets the
+     * first bytes out sooner for large data, but means a partial payload is written if validation fails.
+     * Only the header is sent early. The value is still validated, measured, and buffered in full
+     * before any of it is written.
+     */
+    writeHeaderFirst?: boolean;
+
+    /**
+     * If true, the stream is not closed once the data has been written. This lets you write many
+     * messages to the same stream, but you are then responsible for closing it yourself.
+     */
+    keepOpen?: boolean;
+
+    /**
+     * The maximum number of iterators and readable streams that are read from at once. Any others
+     * wait until one finishes, so an iterator that never ends will stop the ones after it from
+     * being sent. Defaults to no limit.
+     */
+    maxConcurrentStreams?: number;
+
+    /**
+     * The most promises, iterators and readable streams that can be open at once. Unlike
+     * maxConcurrentStreams, going over this is an error rather than a wait, which is useful when
+     * the reader can only keep track of a few streams. Defaults to no limit.
+     */
+    maxStreams?: number;
+
+    /**
+     * If true, every chunk of streamed data is numbered so that the reader can tell if any
+     * were lost or arrived out of order. This adds a little overhead to each chunk.
+     */
+    sequenceNumbers?: boolean;
+
+    /**
+     * If true, iterators and readable streams wait for each chunk to be written before they
+     * read the next one. This stops a fast producer from queueing up data faster than a slow
+     * connection can send it, at the cost of reading from them more slowly. Defaults to false,
+     * where chunks are queued in memory as soon as they are produced.
+     */
+    backpressure?: boolean;
+
+    /**
+     * If true, sub-schemas that appear more than once in the header are written in full the first
+     * time and referred back to after that, which makes the header smaller for schemas that reuse
+     * the same object or union in many places. This doesn't change the schema's hash. These
+     * headers are marked as format version 1, which readers from before this option was added
+     * reject. Defaults to false.
+     */
+    internSchemas?: boolean;
+
+    /**
+     * Cancels serialization when aborted. Nothing more is written, iterators are closed, and
+     * readable streams are cancelled, so their sources stop being read from. The reader sees
+     * any streams that were still open as disconnected.
+     */
+    signal?: AbortSignal;
+};
+
 /**
  * Serializes data using a schema to either a Node.js Writable stream or browser WritableStream.
  * Supports both streaming and buffered serialization with automatic schema negotiation.
@@ -148,6 +321,7 @@ async function browserSerialize<Resolved, S extends Schema<Resolved>>(
  * @param writable - Target stream (Node.js Writable or browser WritableStream)
  * @param data - Data to serialize, must conform to the schema type
  * @param lastUpdateHash - Optional hash of the last schema used, for optimization (generally sent from the client)
+ * @param options - Optional settings for how the data is written
  * @returns Promise that resolves when serialization is complete
  *
  * @example
@@ -172,18 +346,35 @@ async function browserSerialize<Resolved, S extends Schema<Resolved>>(
  * const hash = await getHash(mySchema);
  * await serialize(mySchema, stream, data, hash);
  * ```
+ *
+ * @example
+ * ```typescript
+ * // Write several messages before closing the stream yourself
+ * for (const message of messages) {
+ *   await serialize(mySchema, stream, message, undefined, { keepOpen: true });
+ * }
+ * stream.end();
+ * ```
  */
 export async function serialize<S extends Schema<any>>(
     schema: S,
     writable: Writable | WritableStream<Uint8Array>,
     data: output<S>,
     lastUpdateHash?: string,
+    options: SerializeOptions = {},
 ) {
+    options.signal?.throwIfAborted();
     const ourHash = await getHash(schema);
     const lastUpdateIsUs = lastUpdateHash === ourHash;
 
     if (writable instanceof WritableStream)
-        return browserSerialize(schema, writable, data, lastUpdateIsUs);
+        return browserSerialize(
+            schema,
+            writable,
+            data,
+            lastUpdateIsUs,
+            options,
+        );
 
     // Presume we have a node.js writable stream
 
@@ -191,114 +382,143 @@ export async function serialize<S extends Schema<any>>(
         throw new Error("Buffer is not defined");
     }
 
-    // Figure out the size of the schema.
-    let size = 1;
-    if (!lastUpdateIsUs) {
-        size += schema.schema.length;
+    // If we are asked to, send the header before we walk the value.
+    const header = makeHeader(schema, lastUpdateIsUs, options);
+    let headerSize = header.length;
+    if (options.writeHeaderFirst) {
+        await new Promise<void>((resolve, reject) => {
+            writable.write(header, (err) => {
+                if (err) return reject(err);
+                resolve();
+            });
+        });
+        headerSize = 0;
     }
+
+    // Figure out the size of the data.
     const [valueSize, writeData] = schema.validateAndMakeWriter(data, {});
-    size += valueSize;
 
-    // Create a buffer of that size and write the header.
-    const buffer = Buffer.allocUnsafe(size);
-    if (!lastUpdateIsUs) {
-        buffer[0] = 1; // We need to send the schema.
-        Buffer.from(schema.schema).copy(buffer, 1);
+    // Create a buffer of that size and write the header if it isn't sent.
+    const buffer = Buffer.allocUnsafe(headerSize + valueSize);
+    if (headerSize !== 0) {
+        buffer.set(header, 0);
     }
 
     // Defines the sender queue.
+    const nextSeq = sequenceCounter(options);
     let socketOpen = true;
     let pendingQueue: [number, Buffer][] | null = [];
-    let socketId = 0;
-    let connectedCount = 0;
-    const wg = waitGroup();
-    const createWriteStream = () => {
-        const closer = wg.add();
-        const id = socketId++;
-        let connected = true;
-        connectedCount++;
-
-        return [
-            id,
-            (chunk: Uint8Array | Buffer | null) => {
-                if (!socketOpen) return;
-                if (!connected) throw new Error("Stream is closed");
+    const onAbort = () => {
+        socketOpen = false;
+        pendingQueue = null;
+    };
+    options.signal?.addEventListener("abort", onAbort, { once: true });
+    try {
+        let socketId = 0;
+        let connectedCount = 0;
+        const wg = waitGroup();
+        const createWriteStream = () => {
+            checkStreamLimit(connectedCount, options);
+            const closer = wg.add();
+            const id = socketId++;
+            let connected = true;
+            connectedCount++;
+
+            return [
+                id,
+                (chunk: Uint8Array | Buffer | null) => {
+                    if (!connected) throw new Error("Stream is closed");
+
+                    // Always close, so that a cancelled serialize still finishes.
+                    if (chunk === null) {
+                        connected = false;
+                        connectedCount--;
+                        if (connectedCount === 0) {
+                            pendingQueue = null;
+                            if (socketOpen && !options.keepOpen) {
+                                writable.end();
+                            }
+                        }
+                        closer();
+                        return;
+                    }
+                    if (!socketOpen) return;
 
-                if (chunk === null) {
-                    connected = false;
-                    connectedCount--;
-                    if (connectedCount === 0) {
-                        pendingQueue = null;
-                        writable.end();
+                    if (pendingQueue !== null) {
+                        pendingQueue.push([id, Buffer.from(chunk)]);
+                        return;
                     }
-                    closer();
-                    return;
-                }
 
-                if (pendingQueue !== null) {
-                    pendingQueue.push([id, Buffer.from(chunk)]);
-                    return;
+                    if (socketOpen) {
+                        const frame = makeFrame(id, chunk, nextSeq());
+                        const written = new Promise<void>((resolve) => {
+                            writable.write(frame, (err) => {
+                                if (err) {
+                                    socketOpen = false;
+                                }
+                                resolve();
+                            });
+                        });
+                        if (options.backpressure) return written;
+                    }
+                },
+            ] as [
+                number,
+                (chunk: Uint8Array | Buffer | null) => void | Promise<void>,
+            ];
+        };
+
+        // Write into the context.
+        const baseCtx: WriteContext = {
+            buf: buffer,
+            pos: headerSize,
+            createWriteStream,
+            runStream: streamLimiter(options.maxConcurrentStreams),
+            signal: options.signal,
+        };
+        writeData(baseCtx);
+        checkWrittenSize(baseCtx, buffer.length);
+
+        // Write the buffer. If this fails, stop any streams from queueing more data.
+        await new Promise<void>((resolve, reject) => {
+            writable.write(buffer, (err) => {
+                if (err) {
+                    socketOpen = false;
+                    pendingQueue = null;
+                    return reject(err);
                 }
+                resolve();
+            });
+        });
 
-                if (socketOpen) {
-                    const newAlloc = Buffer.allocUnsafe(chunk.length + 2);
-                    newAlloc[0] = (id >> 8) & 0xff;
-                    newAlloc[1] = id & 0xff;
-                    Buffer.from(chunk).copy(newAlloc, 2);
-                    writable.write(newAlloc, (err) => {
+        // Flush the pending queue.
+        const pq = pendingQueue;
+        pendingQueue = null;
+        if (pq) {
+            for (const [id, chunk] of pq) {
+                if (!socketOpen) break;
+                const frame = makeFrame(id, chunk, nextSeq());
+                await new Promise<void>((resolve, reject) => {
+                    writable.write(frame, (err) => {
                         if (err) {
                             socketOpen = false;
+                            return reject(err);
                         }
+                        resolve();
                     });
-                }
-            },
-        ] as [number, (chunk: Uint8Array | Buffer | null) => void];
-    };
-
-    // Write into the context.
-    const baseCtx: WriteContext = {
-        buf: buffer,
-        pos: 1 + (lastUpdateIsUs ? 0 : schema.schema.length),
-        createWriteStream,
-    };
-    writeData(baseCtx);
-
-    // Write the buffer.
-    await new Promise<void>((resolve, reject) => {
-        writable.write(buffer, (err) => {
-            if (err) return reject(err);
-            resolve();
-        });
-    });
-
-    // Flush the pending queue.
-    const pq = pendingQueue;
-    pendingQueue = null;
-    if (pq) {
-        for (const [id, chunk] of pq) {
-            if (!socketOpen) break;
-            const newAlloc = Buffer.allocUnsafe(chunk.length + 2);
-            newAlloc[0] = (id >> 8) & 0xff;
-            newAlloc[1] = id & 0xff;
-            chunk.copy(newAlloc, 2);
-            await new Promise<void>((resolve, reject) => {
-                writable.write(newAlloc, (err) => {
-                    if (err) {
-                        socketOpen = false;
-                        return reject(err);
-                    }
-                    resolve();
                 });
-            });
+            }
         }
-    }
 
-    // Wait for all streams to close.
-    await wg.wait();
+        // Wait for all streams to close.
+        await wg.wait();
 
-    // Close the connection.
-    if (socketOpen) {
-        writable.end();
+        // Close the connection unless the caller is going to write more.
+        if ((socketOpen || options.signal?.aborted) && !options.keepOpen) {
+            writable.end();
+        }
+    } finally {
+        options.signal?.removeEventListener("abort", onAbort);
     }
 }
 
@@ -383,8 +603,10 @@ export async function serializeToBuffer<S extends Schema<any>>(
         buf: buffer,
         pos: schema.schema.length,
         createWriteStream,
+        runStream: streamLimiter(),
     };
     writeData(baseCtx);
+    checkWrittenSize(baseCtx, buffer.length);
 
     // Flush the pending queue.
     const pq = pendingQueue;
@@ -497,8 +719,10 @@ export async function serializeToUint8Array<S extends Schema<any>>(
         buf: buffer,
         pos: schema.schema.length,
         createWriteStream,
+        runStream: streamLimiter(),
     };
     writeData(baseCtx);
+    checkWrittenSize(baseCtx, buffer.length);
 
     // Flush the pending queue.
     const pq = pendingQueue;
@@ -532,3 +756,143 @@ export async function serializeToUint8Array<S extends Schema<any>>(
 
     return endResult;
 }
+
+/**
+ * Serializes data to a Uint8Array in the same format as {@link serializeToUint8Array}, but
+ * without waiting on anything. This is useful for code that can't be async, such as build
+ * scripts. Since nothing is awaited, data containing streamed types (promises, iterators, and
+ * readable streams) can't be written this way. Reading the result back is still async.
+ *
+ * @template S - The schema type
+ * @param schema - Schema defining the structure and validation rules for the data
+ * @param data - Data to serialize, must conform to the schema type
+ * @returns A Uint8Array containing the serialized binary data
+ * @throws {Error} If the data contains streamed types
+ *
+ * @example
+ * ```typescript
+ * const bytes = serializeToUint8ArraySync(userSchema, { name: "John", age: 30 });
+ * writeFileSync("user.bin", bytes);
+ * ```
+ */
+export function serializeToUint8ArraySync<S extends Schema<any>>(
+    schema: S,
+    data: output<S>,
+): Uint8Array {
+    const [valueSize, writeData] = schema.validateAndMakeWriter(data, {});
+    const buffer = new Uint8Array(schema.schema.length + valueSize);
+    buffer.set(schema.schema, 0);
+
+    const noStreams = (): never => {
+        throw new Error(
+            "Streamed types can't be serialized synchronously, use serializeToUint8Array instead",
+        );
+    };
+    const ctx: WriteContext = {
+        buf: buffer,
+        pos: schema.schema.length,
+        createWriteStream: noStreams,
+        runStream: noStreams,
+    };
+    writeData(ctx);
+    checkWrittenSize(ctx, buffer.length);
+    return buffer;
+}
+
+/**
+ * Serializes data into a single self contained blob, which is useful for storing in a database
+ * or key-value store. The blob starts with its length, followed by the schema and the data, so
+ * it can be read back with {@link readBlob} without knowing anything else about it.
+ *
+ * @template S - The schema type
+ * @param schema - Schema defining the structure and validation rules for the data
+ * @param data - Data to serialize, must conform to the schema type
+ * @returns A Uint8Array containing the blob
+ *
+ * @example
+ * ```typescript
+ * const blob = await serializeToBlob(userSchema, { name: "John", age: 30 });
+ * await kv.put("user:1", blob);
+ * ```
+ */
+export async function serializeToBlob<S extends Schema<any>>(
+    schema: S,
+    data: output<S>,
+): Promise<Uint8Array> {
+    const body = await serializeToUint8Array(schema, data);
+    const blob = new Uint8Array(getRollingUintSize(body.length) + body.length);
+    const pos = writeRollingUintNoAlloc(body.length, blob, 0);
+    blob.set(body, pos);
+    return blob;
+}
+
+/**
+ * A breakdown of how many bytes a value takes up when serialized.
+ */
+export type EncodedSizeReport = {
+    /** The total size of the serialized buffer (schema header and value). */
+    total: number;
+
+    /** The size of the schema header at the start of the buffer. */
+    schema: number;
+
+    /** The size of the encoded value. */
+    value: number;
+
+    /** For object schemas, the encoded size of each top level field. */
+    fields?: Record<string, number>;
+};
+
+/**
+ * Calculates how many bytes data would take up when serialized with {@link serializeToBuffer},
+ * without actually writing anything. For object schemas, the size of each top level field is
+ * also reported. Data sent later on in the stream (such as Promise resolutions or iterator
+ * items) is not included since it is not known up front.
+ *
+ * Note that {@link serialize} writes an extra byte at the start to say if the schema is included.
+ *
+ * @template S - The schema type
+ * @param schema - Schema defining the structure and validation rules for the data
+ * @param data - Data to measure, must conform to the schema type
+ * @returns A report containing the total, schema header, value, and per field sizes
+ *
+ * @example
+ * ```typescript
+ * const userSchema = object({ name: string(), age: uint() });
+ * const report = getEncodedSize(userSchema, { name: "John", age: 30 });
+ * console.log(report.total, report.fields); // 19 { age: 1, name: 5 }
+ * ```
+ */
+export function getEncodedSize<S extends Schema<any>>(
+    schema: S,
+    data: output<S>,
+): EncodedSizeReport {
+    // Validate the whole value first so errors are the same as when serializing.
+    const [valueSize] = schema.validateAndMakeWriter(data, {});
+    const report: EncodedSizeReport = {
+        total: schema.schema.length + valueSize,
+        schema: schema.schema.length,
+        value: valueSize,
+    };
+
+    if (schema.name === "object" || schema.name === "orderedObject") {
+        // Walk the fields in the same order the object writes them in so that
+        // compression tables are filled the same way.
+        const schemas = schema._extraInfo as ObjectSchemas;
+        const keys = Object.keys(schemas);
+        if (schema.name === "object") {
+            keys.sort((a, b) => a.localeCompare(b));
+        }
+        const scratchPad = {};
+        const fields: Record<string, number> = {};
+        for (const key of keys) {
+            fields[key] = schemas[key].validateAndMakeWriter(
+                (data as any)[key],
+                scratchPad,
+            )[0];
+        }
+        report.fields = fields;
+    }
+
+    return report;
+}
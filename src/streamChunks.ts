@@ -0,0 +1,40 @@
+/**
+ * Turns a decoded readable stream into an async iterable of its chunks. This works in every
+ * environment, unlike async iteration of ReadableStream itself, and can be passed to Node's
+ * Readable.from() to pipe the data into a file or HTTP response.
+ *
+ * If the stream errors, the error is thrown from the loop. Breaking out of the loop early cancels
+ * the stream.
+ *
+ * @param stream - The readable stream to read from
+ * @returns An async iterable of the stream's chunks
+ *
+ * @example
+ * ```typescript
+ * const { name, data } = await deserialize(fileSchema, getReader);
+ * await pipeline(Readable.from(streamChunks(data)), createWriteStream(name));
+ * ```
+ */
+export async function* streamChunks(
+    stream: ReadableStream<Uint8Array>,
+): AsyncGenerator<Uint8Array, void, undefined> {
+    const reader = stream.getReader();
+    let finished = false;
+    try {
+        for (;;) {
+            const { done, value } = await reader.read();
+            if (done) break;
+            yield value;
+        }
+        finished = true;
+    } catch (err) {
+        finished = true;
+        throw err;
+    } finally {
+        if (!finished) {
+            // The consumer stopped early, so we don't need the rest.
+            await reader.cancel().catch(() => {});
+        }
+        reader.releaseLock();
+    }
+}
@@ -1,4 +1,5 @@
 import type { ReadContext } from "./ReadContext";
+import { DataError } from "./ReadContext";
 
 export const dataType = {
     // 0x00 is reserved
@@ -34,45 +35,236 @@ export const dataType = {
     any: 0x15,
     compressionTable: 0x16,
     potentiallyFloatString: 0x17,
+    selfDescribingObject: 0x18,
+    dynRecord: 0x19,
+    booleanArray: 0x1a,
+    sparseObject: 0x1b,
+    int128: 0x1c,
+    uint128: 0x1d,
+    tuple: 0x1e,
+    orderedObject: 0x1f,
+    typedArray: 0x20,
+    partial: 0x21,
+    arbitraryBigint: 0x22,
+    float32: 0x23,
+    fixedBytes: 0x24,
+    taggedUnion: 0x25,
+    set: 0x26,
+    recursive: 0x27,
+    recursiveRef: 0x28,
+    timestamp: 0x29,
+
+    // Only written in headers with internSchemas, refers back to an earlier sub-schema by number
+    schemaRef: 0x2a,
+};
+
+export const typedArrayKinds = {
+    int8: Int8Array,
+    int16: Int16Array,
+    uint16: Uint16Array,
+    int32: Int32Array,
+    uint32: Uint32Array,
+    float32: Float32Array,
+    float64: Float64Array,
 };
 
+// The position of each kind is the byte used for it in the schema.
+export const typedArrayKindIds = Object.keys(
+    typedArrayKinds,
+) as (keyof typeof typedArrayKinds)[];
+
+// The scratch pad key deserialize uses to pass the onValue hook down to each schema.
+export const decodeHookKey = Symbol("decodeHook");
+
+// The pairs of recursive() schemas being compared right now, as [ours, theirs] inner schemas.
+// A reference is compatible with another when they point into one of these pairs.
+export const comparingRecursive: [unknown, unknown][] = [];
+
+// The scratch pad key canonical() sets so that schemas write in a single, stable form.
+export const canonicalKey = Symbol("canonical");
+
+export function getRollingUintSize(data: number) {
+    if (data < 0) throw new Error("Data must be a non-negative integer");
+
+    if (data < 0xfd) return 1;
+    if (data <= 0xffff) return 3;
+    if (data <= 0xffffffff) return 5;
+    return 9;
+}
+
+export function writeRollingUintNoAlloc(
+    data: number,
+    u8a: Uint8Array,
+    pos: number,
+) {
+    if (data < 0) throw new Error("Data must be a non-negative integer");
+
+    if (data < 0xfd) {
+        u8a[pos] = data;
+        return pos + 1;
+    }
+    if (data <= 0xffff) {
+        u8a[pos] = 0xfd;
+        u8a[pos + 1] = data & 0xff;
+        u8a[pos + 2] = (data >> 8) & 0xff;
+        return pos + 3;
+    }
+    if (data <= 0xffffffff) {
+        u8a[pos] = 0xfe;
+        u8a[pos + 1] = data & 0xff;
+        u8a[pos + 2] = (data >> 8) & 0xff;
+        u8a[pos + 3] = (data >> 16) & 0xff;
+        u8a[pos + 4] = (data >> 24) & 0xff;
+        return pos + 5;
+    }
+    // Bit operations only work on 32 bits, so split the number into two halves.
+    return writeUint64NoAlloc(
+        data >>> 0,
+        Math.floor(data / 2 ** 32),
+        u8a,
+        pos,
+    );
+}
+
+function writeUint64NoAlloc(
+    low: number,
+    high: number,
+    u8a: Uint8Array,
+    pos: number,
+) {
+    u8a[pos] = 0xff;
+    u8a[pos + 1] = low & 0xff;
+    u8a[pos + 2] = (low >> 8) & 0xff;
+    u8a[pos + 3] = (low >> 16) & 0xff;
+    u8a[pos + 4] = (low >> 24) & 0xff;
+    u8a[pos + 5] = high & 0xff;
+    u8a[pos + 6] = (high >> 8) & 0xff;
+    u8a[pos + 7] = (high >> 16) & 0xff;
+    u8a[pos + 8] = (high >> 24) & 0xff;
+    return pos + 9;
+}
+
+// Values are always written in the smallest form that fits, so a longer form means the data
+// wasn't written by us. Rejecting it keeps each value to exactly one encoding, which canonical
+// output relies on.
+function rejectNonMinimal(start: number): never {
+    throw new DataError("Rolling uint is not in its smallest form", start);
+}
+
 export async function readRollingUintNoAlloc(
     ctx: ReadContext,
 ): Promise<number> {
+    const start = ctx.position;
     const firstByte = await ctx.readByte();
     if (firstByte < 0xfd) {
         return firstByte;
     }
     if (firstByte === 0xfd) {
         const bytes = await ctx.readBytes(2);
-        return bytes[0] | (bytes[1] << 8);
+        const value = bytes[0] | (bytes[1] << 8);
+        if (value < 0xfd) rejectNonMinimal(start);
+        return value;
     }
     if (firstByte === 0xfe) {
         const bytes = await ctx.readBytes(4);
-        return (
+        const value =
             (bytes[0] |
                 (bytes[1] << 8) |
                 (bytes[2] << 16) |
                 (bytes[3] << 24)) >>>
-            0
-        );
+            0;
+        if (value <= 0xffff) rejectNonMinimal(start);
+        return value;
     }
+    const [low, high] = await readUint64NoAlloc(ctx, start);
+    return low + high * 2 ** 32;
+}
+
+async function readUint64NoAlloc(ctx: ReadContext, start: number) {
     const bytes = await ctx.readBytes(8);
-    return (
-        (bytes[0] + (bytes[1] << 8) + (bytes[2] << 16) + (bytes[3] << 24)) >>>
-        (0 +
-            bytes[4] * 2 ** 32 +
-            bytes[5] * 2 ** 40 +
-            bytes[6] * 2 ** 48 +
-            bytes[7] * 2 ** 56)
-    );
+    const low =
+        (bytes[0] | (bytes[1] << 8) | (bytes[2] << 16) | (bytes[3] << 24)) >>>
+        0;
+    const high =
+        (bytes[4] | (bytes[5] << 8) | (bytes[6] << 16) | (bytes[7] << 24)) >>>
+        0;
+    if (high === 0) rejectNonMinimal(start);
+    return [low, high] as const;
+}
+
+// Signed integers are zigzag encoded so small negative numbers stay small, then written as a
+// rolling uint. Zigzagged values past 32 bits can be over the safe integer limit, so those are
+// worked out in two halves to stay exact.
+function zigzag(data: number) {
+    return data < 0 ? -data * 2 - 1 : data * 2;
+}
+
+export function getRollingIntSize(data: number) {
+    return getRollingUintSize(zigzag(data));
+}
+
+export function writeRollingIntNoAlloc(
+    data: number,
+    u8a: Uint8Array,
+    pos: number,
+) {
+    const zigzagged = zigzag(data);
+    if (zigzagged <= 0xffffffff) {
+        return writeRollingUintNoAlloc(zigzagged, u8a, pos);
+    }
+    const magnitude = Math.abs(data);
+    let high = Math.floor(magnitude / 2 ** 31);
+    let low = (magnitude % 2 ** 31) * 2;
+    if (data < 0) {
+        if (low === 0) {
+            low = 0xffffffff;
+            high--;
+        } else {
+            low--;
+        }
+    }
+    return writeUint64NoAlloc(low, high, u8a, pos);
+}
+
+export async function readRollingIntNoAlloc(
+    ctx: ReadContext,
+): Promise<number> {
+    if ((await ctx.peekByte()) !== 0xff) {
+        const zigzagged = await readRollingUintNoAlloc(ctx);
+        return zigzagged % 2 === 1 ? -(zigzagged + 1) / 2 : zigzagged / 2;
+    }
+    const start = ctx.position;
+    await ctx.readByte();
+    const [low, high] = await readUint64NoAlloc(ctx, start);
+    return low % 2 === 1
+        ? -(high * 2 ** 31 + (low + 1) / 2)
+        : high * 2 ** 31 + low / 2;
 }
 
 export type WriteContext = {
     buf: Uint8Array;
     pos: number;
+    // The writer returns a promise when the caller asked for backpressure. Streams should
+    // wait on it before producing the next chunk.
     createWriteStream: () => [
         number,
-        (chunk: Uint8Array | Buffer | null) => void,
+        (chunk: Uint8Array | Buffer | null) => void | Promise<void>,
     ];
+
+    // Starts the task that feeds a stream, waiting if too many are already running.
+    runStream: (task: () => Promise<void>) => void;
+
+    // Aborted when the caller cancels serialization. Streams stop reading from their source
+    // once this fires.
+    signal?: AbortSignal;
 };
+
+// Writers fill a buffer sized from what validation worked out. If the two disagree it is a bug
+// in a schema, so fail loudly rather than sending a truncated or padded payload.
+export function checkWrittenSize(ctx: WriteContext, expected: number) {
+    if (ctx.pos !== expected) {
+        throw new Error(
+            `internal: Wrote up to byte ${ctx.pos} but the buffer is ${expected} bytes`,
+        );
+    }
+}
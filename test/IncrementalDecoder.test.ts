@@ -0,0 +1,33 @@
+import { expect, test } from "vitest";
+import {
+    array,
+    IncrementalDecoder,
+    object,
+    serializeToUint8Array,
+    string,
+    uint,
+} from "../src";
+
+test("completes when fed one byte at a time", async () => {
+    const schema = object({ name: string(), scores: array(uint()) });
+    const value = { name: "hello", scores: [1, 300, 70000] };
+    const bytes = await serializeToUint8Array(schema, value);
+
+    const decoder = new IncrementalDecoder(schema);
+    for (let i = 0; i < bytes.length - 1; i++) {
+        const result = await decoder.feed(bytes.subarray(i, i + 1));
+        expect(result.done).toBe(false);
+    }
+    const result = await decoder.feed(bytes.subarray(bytes.length - 1));
+    expect(result).toEqual({ done: true, value });
+});
+
+test("throws if fed more bytes after the value", async () => {
+    const schema = string();
+    const bytes = await serializeToUint8Array(schema, "a");
+    const decoder = new IncrementalDecoder(schema);
+    expect((await decoder.feed(bytes)).done).toBe(true);
+    await expect(decoder.feed(new Uint8Array([0]))).rejects.toThrow(
+        "The value has already been decoded",
+    );
+});
@@ -0,0 +1,56 @@
+import { describe, expect, test } from "vitest";
+import {
+    array,
+    DataError,
+    OutOfDataError,
+    readSchema,
+    readStaticFile,
+    string,
+    uint,
+} from "../src";
+
+describe("maxDepth", () => {
+    test("rejects a schema of 10,000 nested arrays", async () => {
+        const bytes = new Uint8Array(10001).fill(0x01);
+        bytes[10000] = 0x0a;
+        await expect(readSchema(bytes)).rejects.toThrow(DataError);
+    });
+
+    test("allows schemas up to the limit", async () => {
+        const schema = array(array(uint()));
+        const bytes = new Uint8Array([...schema.schema, 0x01, 0x01, 0x05]);
+        expect(await readStaticFile(schema, bytes, { maxDepth: 3 })).toEqual([
+            [5],
+        ]);
+        await expect(
+            readStaticFile(schema, bytes, { maxDepth: 2 }),
+        ).rejects.toThrow(DataError);
+    });
+});
+
+describe("maxLength", () => {
+    // A string claiming to be 2^40 bytes long.
+    const bytes = new Uint8Array([
+        ...string().schema,
+        0xff,
+        ...[0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00],
+    ]);
+
+    test("rejects a huge length before reading it", async () => {
+        await expect(
+            readStaticFile(string(), bytes, { maxLength: 1024 }),
+        ).rejects.toThrow(DataError);
+    });
+
+    test("doesn't allocate for a huge array length", async () => {
+        const schema = array(uint());
+        const payload = new Uint8Array([
+            ...schema.schema,
+            0xff,
+            ...[0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00],
+        ]);
+        await expect(readStaticFile(schema, payload)).rejects.toThrow(
+            OutOfDataError,
+        );
+    });
+});
@@ -0,0 +1,20 @@
+import { expect, test } from "vitest";
+import { valuesEqual } from "../src";
+
+test("sets don't match the same item twice", () => {
+    expect(valuesEqual(new Set([[1], [1]]), new Set([[1], [2]]))).toBe(false);
+    expect(valuesEqual(new Set([[1], [2]]), new Set([[2], [1]]))).toBe(true);
+});
+
+test("maps with object keys don't match the same entry twice", () => {
+    const a = new Map([
+        [[1], "a"],
+        [[1], "a"],
+    ]);
+    const b = new Map([
+        [[1], "a"],
+        [[2], "a"],
+    ]);
+    expect(valuesEqual(a, b)).toBe(false);
+    expect(valuesEqual(a, new Map(a))).toBe(true);
+});
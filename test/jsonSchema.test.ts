@@ -0,0 +1,25 @@
+import { expect, test } from "vitest";
+import {
+    array,
+    object,
+    pipe,
+    recursive,
+    string,
+    toJsonSchema,
+    uint,
+} from "../src";
+
+test("recursive schemas get the same $defs on every call", () => {
+    const tree = recursive((self) =>
+        object({ value: uint(), children: array(self) }),
+    );
+    const first = toJsonSchema(tree);
+    expect(Object.keys(first.$defs)).toEqual(["recursive0"]);
+    expect(toJsonSchema(tree)).toEqual(first);
+    expect(toJsonSchema(string()).$defs).toBeUndefined();
+});
+
+test("pipe() is described as the schema it wraps", () => {
+    const trimmed = pipe(string(), (str) => str.trim());
+    expect(toJsonSchema(trimmed)).toEqual(toJsonSchema(string()));
+});
@@ -0,0 +1,26 @@
+import { expect, test } from "vitest";
+import {
+    array,
+    fromPrettyJson,
+    nullable,
+    object,
+    optional,
+    string,
+    toPrettyJson,
+} from "../src";
+
+test("optional(nullable()) keeps null apart from a missing field", () => {
+    const schema = object({ name: optional(nullable(string())) });
+    for (const value of [{ name: null }, { name: undefined }, { name: "a" }]) {
+        const json = toPrettyJson(schema, value);
+        expect(fromPrettyJson(schema, json)).toEqual(value);
+    }
+    expect(toPrettyJson(schema, { name: undefined }, 0)).toBe("{}");
+    expect(toPrettyJson(schema, { name: null }, 0)).toBe('{"name":null}');
+});
+
+test("missing optional() items in arrays read back as undefined", () => {
+    const schema = array(optional(string()));
+    const json = toPrettyJson(schema, ["a", undefined]);
+    expect(fromPrettyJson(schema, json)).toEqual(["a", undefined]);
+});
@@ -0,0 +1,54 @@
+import { expect, test } from "vitest";
+import {
+    any,
+    array,
+    nullable,
+    object,
+    readStaticFile,
+    recursive,
+    selfDescribingObject,
+    serializeToUint8Array,
+    uint,
+} from "../src";
+
+test("round trips a small tree", async () => {
+    const tree = recursive((self) =>
+        object({ value: any(), children: array(self) }),
+    );
+    const value = {
+        value: "root",
+        children: [
+            { value: 1, children: [] },
+            {
+                value: [true, "x"],
+                children: [{ value: null, children: [] }],
+            },
+        ],
+    };
+    const bytes = await serializeToUint8Array(tree, value);
+    expect(await readStaticFile(tree, bytes)).toEqual(value);
+});
+
+test("round trips a self describing field that refers to itself", async () => {
+    const list = recursive((self) =>
+        selfDescribingObject({ value: uint(), next: nullable(self) }),
+    );
+    const value = { value: 1, next: { value: 2, next: null } };
+    const bytes = await serializeToUint8Array(list, value);
+    expect(await readStaticFile(list, bytes)).toEqual(value);
+});
+
+test("rejects values nested deeper than maxDepth", async () => {
+    const list = recursive((self) =>
+        object({ value: uint(), next: nullable(self) }),
+    );
+    let value: any = null;
+    for (let i = 0; i < 200; i++) value = { value: i, next: value };
+    const bytes = await serializeToUint8Array(list, value);
+    await expect(readStaticFile(list, bytes)).rejects.toThrow(
+        "nested more than 128 levels deep",
+    );
+    expect(await readStaticFile(list, bytes, { maxDepth: 1000 })).toEqual(
+        value,
+    );
+});
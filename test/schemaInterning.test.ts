@@ -0,0 +1,93 @@
+import { describe, expect, test } from "vitest";
+import {
+    array,
+    boolean,
+    deserialize,
+    inspectHeader,
+    object,
+    serialize,
+    string,
+    uint,
+    type SerializeOptions,
+} from "../src";
+
+const item = object({
+    id: uint(),
+    name: string(),
+    active: boolean(),
+    tags: array(string()),
+    score: uint(),
+});
+
+const fields: Record<string, typeof item> = {};
+for (let i = 0; i < 10; i++) fields[`item${i}`] = item;
+const schema = object(fields);
+
+const value: Record<string, any> = {};
+for (let i = 0; i < 10; i++) {
+    value[`item${i}`] = {
+        id: i,
+        name: `item ${i}`,
+        active: i % 2 === 0,
+        tags: ["a", "b"],
+        score: i * 10,
+    };
+}
+
+// Returns the header and the whole payload.
+async function write(options: SerializeOptions) {
+    const chunks: Uint8Array[] = [];
+    const writable = new WritableStream<Uint8Array>({
+        write(chunk) {
+            chunks.push(chunk);
+        },
+    });
+    await serialize(schema, writable, value as any, undefined, {
+        ...options,
+        writeHeaderFirst: true,
+    });
+    const payload = new Uint8Array(
+        chunks.reduce((size, chunk) => size + chunk.length, 0),
+    );
+    let pos = 0;
+    for (const chunk of chunks) {
+        payload.set(chunk, pos);
+        pos += chunk.length;
+    }
+    return { header: chunks[0], payload };
+}
+
+function streamOf(bytes: Uint8Array) {
+    return new ReadableStream<Uint8Array>({
+        start(controller) {
+            controller.enqueue(bytes);
+            controller.close();
+        },
+    });
+}
+
+describe("internSchemas", () => {
+    test("makes a header that repeats an object smaller", async () => {
+        const plain = await write({});
+        const interned = await write({ internSchemas: true });
+        expect(plain.header.length).toBe(1 + schema.schema.length);
+        expect(interned.header.length).toBeLessThan(
+            plain.header.length / 3,
+        );
+    });
+
+    test("marks interned headers as version 1", async () => {
+        const plain = await write({});
+        const interned = await write({ internSchemas: true });
+        expect(inspectHeader(plain.payload).flags.version).toBe(0);
+        expect(inspectHeader(interned.payload).flags.version).toBe(1);
+    });
+
+    test("reads back the same value", async () => {
+        const { payload } = await write({ internSchemas: true });
+        const result = await deserialize(schema, async () =>
+            streamOf(payload),
+        );
+        expect(result).toEqual(value);
+    });
+});
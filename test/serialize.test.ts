@@ -0,0 +1,40 @@
+import { describe, expect, test } from "vitest";
+import { object, serialize, string } from "../src";
+
+// Records every chunk written, and how many had been written when the value was first read.
+function instrumented() {
+    const writes: Uint8Array[] = [];
+    let writesWhenRead = -1;
+    const writable = new WritableStream<Uint8Array>({
+        write(chunk) {
+            writes.push(chunk);
+        },
+    });
+    const data = {
+        get name() {
+            if (writesWhenRead === -1) writesWhenRead = writes.length;
+            return "hello";
+        },
+    };
+    return { writes, writable, data, writesWhenRead: () => writesWhenRead };
+}
+
+describe("writeHeaderFirst", () => {
+    const schema = object({ name: string() });
+
+    test("writes the header before the value is walked", async () => {
+        const { writes, writable, data, writesWhenRead } = instrumented();
+        await serialize(schema, writable, data, undefined, {
+            writeHeaderFirst: true,
+        });
+        expect(writesWhenRead()).toBe(1);
+        expect(writes[0]).toEqual(new Uint8Array([0x01, ...schema.schema]));
+    });
+
+    test("writes nothing before the value is walked by default", async () => {
+        const { writes, writable, data, writesWhenRead } = instrumented();
+        await serialize(schema, writable, data);
+        expect(writesWhenRead()).toBe(0);
+        expect(writes.length).toBe(1);
+    });
+});